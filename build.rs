@@ -0,0 +1,41 @@
+//!
+//! Build script
+//!
+//! With the `bundled` feature, the postgresql binaries archive referenced by the
+//! `PG_EMBED_BUNDLED_ARCHIVE` environment variable is embedded into the library.
+//!
+use std::env;
+use std::path::PathBuf;
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=PG_EMBED_BUNDLED_ARCHIVE");
+    if env::var_os("CARGO_FEATURE_BUNDLED").is_none() {
+        return;
+    }
+    let archive = env::var_os("PG_EMBED_BUNDLED_ARCHIVE")
+        .map(PathBuf::from)
+        .expect(
+            "the bundled feature requires PG_EMBED_BUNDLED_ARCHIVE to be set to the absolute path \
+         of a postgresql binaries archive",
+        );
+    let archive = archive.canonicalize().unwrap_or_else(|e| {
+        panic!(
+            "invalid PG_EMBED_BUNDLED_ARCHIVE {}: {}",
+            archive.display(),
+            e
+        )
+    });
+    let file_name = archive
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    println!("cargo:rerun-if-changed={}", archive.display());
+    println!(
+        "cargo:rustc-env=PG_EMBED_BUNDLED_ARCHIVE_PATH={}",
+        archive.display()
+    );
+    println!(
+        "cargo:rustc-env=PG_EMBED_BUNDLED_ARCHIVE_NAME={}",
+        file_name
+    );
+}
@@ -0,0 +1,99 @@
+//!
+//! pg-embed macros
+//!
+//! Re-exported by pg-embed with the `macros` feature, use `#[pg_embed::test]`.
+//!
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::spanned::Spanned;
+use syn::{parse_macro_input, FnArg, ItemFn};
+
+///
+/// Run an async test against a fresh database
+///
+/// The test runs on a tokio runtime with a database copied from the template database of
+/// a postgresql server shared by the tests of the test binary. The optional test parameter
+/// receives the database, its type must implement
+/// `pg_embed::test_support::PgTestArg` (e.g. `String` for the database uri).
+/// The database is dropped after the test, also if the test panics.
+///
+/// ```rust, ignore
+/// #[pg_embed::test]
+/// async fn users_table(db_uri: String) {
+///     // ...
+/// }
+/// ```
+///
+#[proc_macro_attribute]
+pub fn test(args: TokenStream, item: TokenStream) -> TokenStream {
+    if !args.is_empty() {
+        return syn::Error::new(
+            proc_macro2::Span::call_site(),
+            "#[pg_embed::test] takes no arguments",
+        )
+        .to_compile_error()
+        .into();
+    }
+    let input = parse_macro_input!(item as ItemFn);
+    let sig = &input.sig;
+    if sig.asyncness.is_none() {
+        return syn::Error::new(
+            sig.fn_token.span(),
+            "#[pg_embed::test] requires an async fn",
+        )
+        .to_compile_error()
+        .into();
+    }
+    if sig.inputs.len() > 1 {
+        return syn::Error::new(
+            sig.inputs.span(),
+            "#[pg_embed::test] takes at most one database parameter",
+        )
+        .to_compile_error()
+        .into();
+    }
+    let arg = match sig.inputs.first() {
+        None => None,
+        Some(FnArg::Typed(arg)) => Some(&arg.ty),
+        Some(receiver) => {
+            return syn::Error::new(receiver.span(), "#[pg_embed::test] requires a free fn")
+                .to_compile_error()
+                .into();
+        }
+    };
+    let attrs = &input.attrs;
+    let vis = &input.vis;
+    let name = &sig.ident;
+    let inputs = &sig.inputs;
+    let output = &sig.output;
+    let body = &input.block;
+    let call = match arg {
+        Some(ty) => quote! {
+            let arg = <#ty as ::pg_embed::test_support::PgTestArg>::from_database(&database)
+                .await
+                .expect("could not prepare the test database parameter");
+            ::pg_embed::test_support::catch_unwind(test(arg)).await
+        },
+        None => quote! {
+            ::pg_embed::test_support::catch_unwind(test()).await
+        },
+    };
+    let expanded = quote! {
+        #(#attrs)*
+        #[::pg_embed::test_support::tokio::test(crate = "::pg_embed::test_support::tokio")]
+        #vis async fn #name() #output {
+            async fn test(#inputs) #output #body
+
+            let database = ::pg_embed::test_support::test_database()
+                .await
+                .expect("could not create the test database");
+            let result = { #call };
+            ::pg_embed::test_support::teardown(database).await;
+            match result {
+                Ok(output) => output,
+                Err(panic) => ::std::panic::resume_unwind(panic),
+            }
+        }
+    };
+    expanded.into()
+}
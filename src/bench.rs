@@ -0,0 +1,168 @@
+//!
+//! Benchmarking
+//!
+//! Run the bundled pgbench against the embedded server.
+//!
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::pg_errors::{PgEmbedError, PgEmbedErrorType};
+use crate::pg_types::PgResult;
+use crate::postgres::PgEmbed;
+
+///
+/// Options of [PgEmbed::pgbench_run]
+///
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PgBenchOptions {
+    /// number of concurrent clients
+    pub clients: u32,
+    /// number of worker threads
+    pub threads: u32,
+    /// benchmark duration, takes precedence over [PgBenchOptions::transactions]
+    #[serde(with = "humantime_serde")]
+    pub duration: Option<Duration>,
+    /// number of transactions per client
+    pub transactions: Option<u32>,
+    /// run the built-in select only script instead of the tpc-b like script
+    pub select_only: bool,
+    /// custom benchmark script
+    pub script: Option<PathBuf>,
+}
+
+impl Default for PgBenchOptions {
+    fn default() -> Self {
+        PgBenchOptions {
+            clients: 1,
+            threads: 1,
+            duration: None,
+            transactions: Some(1000),
+            select_only: false,
+            script: None,
+        }
+    }
+}
+
+///
+/// Result of a pgbench run
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgBenchResult {
+    /// transactions per second (without the initial connection time)
+    pub tps: f64,
+    /// average latency in milliseconds
+    pub latency_average_ms: Option<f64>,
+    /// latency standard deviation in milliseconds
+    pub latency_stddev_ms: Option<f64>,
+    /// number of processed transactions
+    pub transactions: Option<u64>,
+}
+
+impl PgBenchResult {
+    ///
+    /// Parse the summary printed by pgbench
+    ///
+    /// Older pgbench versions print the tps including and excluding the connection
+    /// establishment, the latter is used.
+    ///
+    /// Returns the result on success, `None` if the output contains no tps.
+    ///
+    pub fn parse(output: &str) -> Option<Self> {
+        let value = |line: &str, prefix: &str| -> Option<f64> {
+            line.strip_prefix(prefix)?
+                .split_whitespace()
+                .next()?
+                .parse::<f64>()
+                .ok()
+        };
+        let mut result = PgBenchResult {
+            tps: 0.0,
+            latency_average_ms: None,
+            latency_stddev_ms: None,
+            transactions: None,
+        };
+        let mut tps = None;
+        for line in output.lines().map(str::trim) {
+            if let Some(line_tps) = value(line, "tps = ") {
+                tps = Some(line_tps);
+            } else if let Some(latency) = value(line, "latency average = ") {
+                result.latency_average_ms = Some(latency);
+            } else if let Some(stddev) = value(line, "latency stddev = ") {
+                result.latency_stddev_ms = Some(stddev);
+            } else if let Some(processed) =
+                line.strip_prefix("number of transactions actually processed: ")
+            {
+                // `1000` or `1000/1000`
+                result.transactions = processed
+                    .split('/')
+                    .next()
+                    .and_then(|count| count.trim().parse::<u64>().ok());
+            }
+        }
+        result.tps = tps?;
+        Some(result)
+    }
+}
+
+impl PgEmbed {
+    ///
+    /// Initialize the pgbench tables of a database
+    ///
+    /// `scale` multiplies the number of generated rows (100000 accounts per scale unit).
+    ///
+    pub async fn pgbench_init(&self, db_name: &str, scale: u32) -> PgResult<()> {
+        let scale = scale.to_string();
+        self.run_client_tool("pgbench", &["-i", "-q", "-s", &scale, db_name])
+            .await?;
+        Ok(())
+    }
+
+    ///
+    /// Run pgbench against a database
+    ///
+    /// Returns the parsed result on success, otherwise returns an error.
+    ///
+    pub async fn pgbench_run(
+        &self,
+        db_name: &str,
+        options: &PgBenchOptions,
+    ) -> PgResult<PgBenchResult> {
+        let mut args = vec![
+            "-c".to_string(),
+            options.clients.to_string(),
+            "-j".to_string(),
+            options.threads.to_string(),
+        ];
+        match (options.duration, options.transactions) {
+            (Some(duration), _) => args.extend(vec![
+                "-T".to_string(),
+                duration.as_secs().max(1).to_string(),
+            ]),
+            (None, Some(transactions)) => {
+                args.extend(vec!["-t".to_string(), transactions.to_string()])
+            }
+            (None, None) => {}
+        }
+        if options.select_only {
+            args.push("-S".to_string());
+        }
+        if let Some(script) = &options.script {
+            args.push("-f".to_string());
+            args.push(script.to_string_lossy().to_string());
+        }
+        args.push(db_name.to_string());
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let output = self.run_client_tool("pgbench", &args).await?;
+        PgBenchResult::parse(&output.stdout).ok_or_else(|| PgEmbedError {
+            error_type: PgEmbedErrorType::PgProcessError,
+            source: None,
+            message: Some(format!(
+                "unexpected pgbench output {}",
+                output.stdout.trim()
+            )),
+        })
+    }
+}
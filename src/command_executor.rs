@@ -1,10 +1,12 @@
 //!
 //! Process command creation and execution
 //!
+use std::collections::HashMap;
 use std::error::Error;
-use std::ffi::OsStr;
+use std::ffi::{OsStr, OsString};
 use std::marker;
-use std::process::Stdio;
+use std::process::{ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
 
 use async_trait::async_trait;
 use log;
@@ -13,15 +15,55 @@ use tokio::process::Child;
 use tokio::sync::mpsc::{Receiver, Sender};
 use tokio::time::Duration;
 
+/// Time to wait for the remaining output of a failed process
+const FAILURE_OUTPUT_TIMEOUT: Duration = Duration::from_secs(1);
+
 ///
 /// Output logging type
 ///
+/// The output stream of a process line, `Info` for stdout and `Error` for stderr.
+///
 #[derive(Debug)]
 pub enum LogType {
     Info,
     Error,
 }
 
+///
+/// Log level of a stderr output line
+///
+/// Postgresql server lines are mapped by their severity (`LOG:`, `WARNING:`, `ERROR:` ...),
+/// client executable lines by their prefix (`initdb: warning: ...`). The first severity of
+/// the line is used, lines without severity are warnings.
+///
+pub(crate) fn stderr_level(line: &str) -> log::Level {
+    const SEVERITIES: [(&str, log::Level); 16] = [
+        ("panic:", log::Level::Error),
+        ("fatal:", log::Level::Error),
+        ("error:", log::Level::Error),
+        ("warning:", log::Level::Warn),
+        ("log:", log::Level::Info),
+        ("info:", log::Level::Info),
+        ("notice:", log::Level::Info),
+        ("detail:", log::Level::Info),
+        ("hint:", log::Level::Info),
+        ("statement:", log::Level::Info),
+        ("context:", log::Level::Info),
+        ("debug1:", log::Level::Debug),
+        ("debug2:", log::Level::Debug),
+        ("debug3:", log::Level::Debug),
+        ("debug4:", log::Level::Debug),
+        ("debug5:", log::Level::Debug),
+    ];
+    let lowercase = line.to_lowercase();
+    SEVERITIES
+        .iter()
+        .filter_map(|(severity, level)| lowercase.find(severity).map(|index| (index, *level)))
+        .min_by_key(|(index, _)| *index)
+        .map(|(_, level)| level)
+        .unwrap_or(log::Level::Warn)
+}
+
 ///
 /// Child process status
 ///
@@ -38,6 +80,14 @@ where
     fn error_type(&self) -> E;
     /// wrap error
     fn wrap_error<F: Error + Sync + Send + 'static>(&self, error: F, message: Option<String>) -> E;
+    /// process failure error including the command line and the collected stdout / stderr output
+    fn failure_error(&self, _command_line: &str, _exit_status: ExitStatus, _output: &str) -> E {
+        self.error_type()
+    }
+    /// log target of the process output stream
+    fn log_target(&self, _stream: &LogType) -> &'static str {
+        "pg_embed"
+    }
 }
 
 ///
@@ -47,6 +97,7 @@ where
 pub struct LogOutputData {
     line: String,
     log_type: LogType,
+    target: &'static str,
 }
 
 ///
@@ -62,7 +113,14 @@ where
     ///
     /// Create a new async command
     ///
-    fn new<A, B>(executable_path: &OsStr, args: A, process_type: P) -> Result<Self, E>
+    /// The child process environment is cleared and replaced by `envs`.
+    ///
+    fn new<A, B>(
+        executable_path: &OsStr,
+        args: A,
+        envs: &HashMap<OsString, OsString>,
+        process_type: P,
+    ) -> Result<Self, E>
     where
         A: IntoIterator<Item = B>,
         B: AsRef<OsStr>;
@@ -87,6 +145,8 @@ where
 {
     /// Process command
     command: tokio::process::Command,
+    /// Process command line, reported on failure
+    command_line: String,
     /// Process child
     process: Child,
     /// Process type
@@ -110,24 +170,56 @@ where
             .map_err(|_| process_type.error_type())
     }
 
+    /// Format the command line
+    fn format_command_line<A, B>(executable_path: &OsStr, args: A) -> String
+    where
+        A: IntoIterator<Item = B>,
+        B: AsRef<OsStr>,
+    {
+        std::iter::once(executable_path.to_string_lossy().to_string())
+            .chain(
+                args.into_iter()
+                    .map(|arg| arg.as_ref().to_string_lossy().to_string()),
+            )
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
     /// Generate a command
-    fn generate_command<A, B>(executable_path: &OsStr, args: A) -> tokio::process::Command
+    fn generate_command<A, B>(
+        executable_path: &OsStr,
+        args: A,
+        envs: &HashMap<OsString, OsString>,
+    ) -> tokio::process::Command
     where
         A: IntoIterator<Item = B>,
         B: AsRef<OsStr>,
     {
         let mut command = tokio::process::Command::new(executable_path);
-        command.args(args);
+        command.args(args).env_clear().envs(envs);
         command
     }
 
     /// Handle process output
-    async fn handle_output<R: AsyncRead + Unpin>(data: R, sender: Sender<LogOutputData>) -> () {
+    async fn handle_output<R: AsyncRead + Unpin>(
+        data: R,
+        sender: Sender<LogOutputData>,
+        collected: Arc<Mutex<Vec<String>>>,
+        stream: LogType,
+        target: &'static str,
+    ) -> () {
         let mut lines = BufReader::new(data).lines();
         while let Some(line) = lines.next_line().await.expect("error handling output") {
+            if let Ok(mut collected) = collected.lock() {
+                collected.push(line.clone());
+            }
             let io_data = LogOutputData {
                 line,
-                log_type: LogType::Info,
+                log_type: match stream {
+                    LogType::Info => LogType::Info,
+                    LogType::Error => LogType::Error,
+                },
+                target,
             };
             sender
                 .send(io_data)
@@ -139,58 +231,85 @@ where
     /// Log process output
     async fn log_output(mut receiver: Receiver<LogOutputData>) -> () {
         while let Some(data) = receiver.recv().await {
-            match data.log_type {
-                LogType::Info => {
-                    log::info!("{}", data.line);
-                }
-                LogType::Error => {
-                    log::error!("{}", data.line);
-                }
-            }
+            let level = match data.log_type {
+                LogType::Info => log::Level::Info,
+                LogType::Error => stderr_level(&data.line),
+            };
+            log::log!(target: data.target, level, "{}", data.line);
         }
     }
 
     /// Run process
-    async fn run_process(&mut self) -> Result<S, E> {
-        let exit_status = self
-            .process
+    async fn run_process(&mut self) -> Result<ExitStatus, E> {
+        self.process
             .wait()
             .await
-            .map_err(|e| self.process_type.wrap_error(e, None))?;
-        if exit_status.success() {
-            Ok(self.process_type.status_exit())
-        } else {
-            Err(self.process_type.error_type())
-        }
+            .map_err(|e| self.process_type.wrap_error(e, None))
     }
 
     #[cfg(not(target_os = "windows"))]
     async fn command_execution(&mut self) -> Result<S, E> {
         let (sender, receiver) = tokio::sync::mpsc::channel::<LogOutputData>(1000);
-        let res = self.run_process().await;
+        let collected = Arc::new(Mutex::new(Vec::new()));
         let stdout = self.process.stdout.take().unwrap();
         let stderr = self.process.stderr.take().unwrap();
         let tx = sender.clone();
-        let _ = tokio::task::spawn(async { Self::handle_output(stdout, tx).await });
-        let _ = tokio::task::spawn(async { Self::handle_output(stderr, sender).await });
+        let stdout_collected = collected.clone();
+        let stderr_collected = collected.clone();
+        let stdout_target = self.process_type.log_target(&LogType::Info);
+        let stderr_target = self.process_type.log_target(&LogType::Error);
+        let stdout_handle = tokio::task::spawn(async move {
+            Self::handle_output(stdout, tx, stdout_collected, LogType::Info, stdout_target).await
+        });
+        let stderr_handle = tokio::task::spawn(async move {
+            Self::handle_output(
+                stderr,
+                sender,
+                stderr_collected,
+                LogType::Error,
+                stderr_target,
+            )
+            .await
+        });
         let _ = tokio::task::spawn(async { Self::log_output(receiver).await });
-        res
+        let exit_status = self.run_process().await?;
+        if exit_status.success() {
+            return Ok(self.process_type.status_exit());
+        }
+        // a server started by the failed process may keep the pipes open
+        let _ = tokio::time::timeout(FAILURE_OUTPUT_TIMEOUT, async {
+            let _ = stdout_handle.await;
+            let _ = stderr_handle.await;
+        })
+        .await;
+        let output = collected
+            .lock()
+            .map(|collected| collected.join("\n"))
+            .unwrap_or_default();
+        Err(self
+            .process_type
+            .failure_error(&self.command_line, exit_status, &output))
     }
 
     #[cfg(target_os = "windows")]
     async fn command_execution(&mut self) -> Result<S, E> {
         //TODO: find another way to use stderr on windows
         // let (sender, receiver) = tokio::sync::mpsc::channel::<LogOutputData>(1000);
-        let res = self.run_process().await;
+        let exit_status = self.run_process().await?;
         // let stdout = self.process.stdout.take().unwrap();
         // let stderr = self.process.stderr.take().unwrap();
         // let tx = sender.clone();
         // let _ = tokio::task::spawn(async { Self::handle_output(stdout, tx).await });
         // let _ = tokio::task::spawn(async { Self::handle_output(stderr, sender).await });
         // let _ = tokio::task::spawn(async { Self::log_output(receiver).await });
-        res
+        if exit_status.success() {
+            Ok(self.process_type.status_exit())
+        } else {
+            Err(self
+                .process_type
+                .failure_error(&self.command_line, exit_status, ""))
+        }
     }
-
 }
 
 #[async_trait]
@@ -200,15 +319,23 @@ where
     E: Error + Send,
     P: ProcessStatus<S, E> + Send,
 {
-    fn new<A, B>(executable_path: &OsStr, args: A, process_type: P) -> Result<Self, E>
+    fn new<A, B>(
+        executable_path: &OsStr,
+        args: A,
+        envs: &HashMap<OsString, OsString>,
+        process_type: P,
+    ) -> Result<Self, E>
     where
         A: IntoIterator<Item = B>,
         B: AsRef<OsStr>,
     {
-        let mut command = Self::generate_command(executable_path, args);
+        let args: Vec<B> = args.into_iter().collect();
+        let command_line = Self::format_command_line(executable_path, args.iter());
+        let mut command = Self::generate_command(executable_path, args, envs);
         let process = Self::init(&mut command, &process_type)?;
         Ok(AsyncCommandExecutor {
             command,
+            command_line,
             process,
             process_type,
             _marker_s: Default::default(),
@@ -33,12 +33,21 @@
 //!      pg-embed = "0.6"
 //!      ```
 //!
+//!      *Library with refinery migration support*
+//!
+//!      ```toml
+//!      # Cargo.toml
+//!      [dependencies]
+//!      pg-embed = { version = "0.6", default-features = false, features = ["rt_tokio", "migrate_refinery"] }
+//!      ```
+//!
 //!
 //! # Examples
 //!
 //! ```rust, ignore
 //!
-//! use pg_embed::postgres::{PgEmbed, PgSettings, PgAuthMethod};
+//! use pg_embed::postgres::{PgEmbed, PgSettings};
+//! use pg_embed::pg_enums::{PgAuthMethod, PgPassword};
 //! use pg_embed::pg_fetch;
 //! use pg_embed::pg_fetch::{PgFetchSettings, PG_V13};
 //! use std::time::Duration;
@@ -47,10 +56,12 @@
 //! /// Postgresql settings
 //! let pg_settings = PgSettings{
 //! // Where to store the postgresql database
-//! database_dir: PathBuf::from("data/db"),
+//! // (`None` creates a temporary directory, which is removed on drop)
+//! database_dir: Some(PathBuf::from("data/db")),
 //! port: 5432,
 //! user: "postgres".to_string(),
-//! password: "password".to_string(),
+//! // password value, random password or password read from an environment variable
+//! password: PgPassword::Value("password".to_string()),
 //! // authentication method
 //! auth_method: PgAuthMethod::Plain,
 //! // If persistent is false clean up files and directories on drop, otherwise keep them
@@ -63,6 +74,8 @@
 //! // specified here with `Some(PathBuf(path_to_dir)), otherwise `None` to run no migrations.
 //! // To enable migrations view the **Usage** section for details
 //! migration_dir: None,
+//! // Remaining settings (cache directory, child process environment, ...)
+//! ..Default::default()
 //! };
 //!
 //! /// Postgresql binaries download settings
@@ -84,15 +97,12 @@
 //!      pg.start_db().await;
 //!
 //!      // create a new database
-//!      // to enable migrations view the [Usage] section for details
 //!      pg.create_database("database_name").await;
 //!
 //!      // drop a database
-//!      // to enable migrations view [Usage] for details
 //!      pg.drop_database("database_name").await;
 //!
 //!      // check database existence
-//!      // to enable migrations view [Usage] for details
 //!      pg.database_exists("database_name").await;
 //!
 //!      // run migration sql scripts
@@ -175,12 +185,30 @@ compile_error!(
      'rt_async_std', 'rt_async_std_migrate'] can be enabled"
 );
 
+pub mod bench;
 pub mod command_executor;
+pub mod maintenance;
 pub mod pg_access;
+pub mod pg_clock;
 pub mod pg_commands;
+pub mod pg_config;
 pub mod pg_enums;
 pub mod pg_errors;
+pub mod pg_factory;
+pub mod pg_fault;
 pub mod pg_fetch;
+pub mod pg_fixture;
+pub mod pg_http;
+pub mod pg_log;
+pub mod pg_metrics;
+pub mod pg_otel;
+pub mod pg_provider;
+pub mod pg_seed;
+pub mod pg_shared;
 pub mod pg_types;
 pub mod pg_unpack;
 pub mod postgres;
+pub mod test_support;
+
+#[cfg(feature = "macros")]
+pub use pg_embed_macros::test;
@@ -0,0 +1,278 @@
+//!
+//! Maintenance utilities
+//!
+//! Clean up database directories and postgresql processes left behind by crashed
+//! (e.g. killed test) processes.
+//!
+use std::path::{Path, PathBuf};
+
+use futures::TryFutureExt;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::pg_errors::{PgEmbedError, PgEmbedErrorType};
+use crate::pg_types::PgResult;
+
+const PG_EMBED_REGISTRY_DIR_NAME: &str = "pg-embed-registry";
+
+///
+/// Registry entry of a non-persistent database cluster
+///
+/// Written when a PgEmbed instance is created and removed after its clean up on drop,
+/// so remaining entries belong to instances which never cleaned up.
+///
+#[derive(Debug, Serialize, Deserialize)]
+struct PgRegistryEntry {
+    /// id of the process owning the cluster
+    pid: u32,
+    /// database directory
+    database_dir: PathBuf,
+    /// password file
+    pw_file: PathBuf,
+    /// temporary directory containing the database directory
+    temp_dir: Option<PathBuf>,
+    /// pg_ctl executable used to stop a still running server
+    pg_ctl: PathBuf,
+}
+
+///
+/// Per-user directory `name` in the temporary directory
+///
+/// The temporary directory is shared by all users, so on unix the directory
+/// (`{name}-{uid}`) is created with mode 0700 and directories owned by another user
+/// or accessible by other users are refused.
+///
+/// Returns the directory path on success, otherwise returns an error.
+///
+pub(crate) fn private_temp_dir(name: &str) -> PgResult<PathBuf> {
+    let to_dir_error = |e: std::io::Error| PgEmbedError {
+        error_type: PgEmbedErrorType::DirCreationError,
+        source: Some(Box::new(e)),
+        message: None,
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+        let uid = unsafe { libc::geteuid() };
+        let dir = std::env::temp_dir().join(format!("{}-{}", name, uid));
+        match std::fs::DirBuilder::new().mode(0o700).create(&dir) {
+            Err(e) if e.kind() != std::io::ErrorKind::AlreadyExists => return Err(to_dir_error(e)),
+            _ => {}
+        }
+        let metadata = std::fs::symlink_metadata(&dir).map_err(to_dir_error)?;
+        if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o077 != 0 {
+            return Err(PgEmbedError {
+                error_type: PgEmbedErrorType::DirCreationError,
+                source: None,
+                message: Some(format!(
+                    "refusing to use {}, it is not a private directory of the current user",
+                    dir.display()
+                )),
+            });
+        }
+        Ok(dir)
+    }
+    #[cfg(not(unix))]
+    {
+        let dir = std::env::temp_dir().join(name);
+        std::fs::create_dir_all(&dir).map_err(to_dir_error)?;
+        Ok(dir)
+    }
+}
+
+///
+/// Per-user directory of the registry entries
+///
+fn registry_dir() -> PgResult<PathBuf> {
+    private_temp_dir(PG_EMBED_REGISTRY_DIR_NAME)
+}
+
+///
+/// Register a non-persistent database cluster
+///
+/// Returns the registry entry path on success, otherwise returns an error.
+///
+pub(crate) fn register_instance(
+    database_dir: &Path,
+    pw_file: &Path,
+    temp_dir: Option<&Path>,
+    pg_ctl: &Path,
+) -> PgResult<PathBuf> {
+    let to_registry_error = |e: std::io::Error| PgEmbedError {
+        error_type: PgEmbedErrorType::WriteFileError,
+        source: Some(Box::new(e)),
+        message: Some(String::from("could not register the database directory")),
+    };
+    let registry_dir = registry_dir()?;
+    let entry = PgRegistryEntry {
+        pid: std::process::id(),
+        database_dir: database_dir.to_path_buf(),
+        pw_file: pw_file.to_path_buf(),
+        temp_dir: temp_dir.map(Path::to_path_buf),
+        pg_ctl: pg_ctl.to_path_buf(),
+    };
+    let content = toml::to_string(&entry).map_err(|e| PgEmbedError {
+        error_type: PgEmbedErrorType::WriteFileError,
+        source: Some(Box::new(e)),
+        message: None,
+    })?;
+    let entry_file = tempfile::Builder::new()
+        .prefix(&format!("{}-", entry.pid))
+        .suffix(".toml")
+        .tempfile_in(&registry_dir)
+        .map_err(to_registry_error)?;
+    std::fs::write(entry_file.path(), content).map_err(to_registry_error)?;
+    let (_, entry_path) = entry_file.keep().map_err(|e| to_registry_error(e.error))?;
+    Ok(entry_path)
+}
+
+///
+/// Remove the registry entry of a cleaned up database cluster
+///
+pub(crate) fn unregister_instance(entry_path: &Path) {
+    let _ = std::fs::remove_file(entry_path);
+}
+
+///
+/// Clean up orphaned database clusters
+///
+/// Scans the registry of non-persistent database clusters of the current user for clusters
+/// whose owning process no longer runs, stops their still running postgresql servers and
+/// removes the leftover database directories and password files. Clusters which can't be
+/// removed are logged and kept in the registry.
+///
+/// Returns the removed database directories on success, otherwise returns an error.
+///
+pub async fn cleanup_orphans() -> PgResult<Vec<PathBuf>> {
+    let registry_dir = registry_dir()?;
+    let mut removed = Vec::new();
+    let mut entries = match tokio::fs::read_dir(&registry_dir).await {
+        Ok(entries) => entries,
+        Err(_) => return Ok(removed),
+    };
+    while let Some(entry) = entries
+        .next_entry()
+        .map_err(|e| PgEmbedError {
+            error_type: PgEmbedErrorType::ReadFileError,
+            source: Some(Box::new(e)),
+            message: None,
+        })
+        .await?
+    {
+        let entry_path = entry.path();
+        let registered = match tokio::fs::read_to_string(&entry_path).await {
+            Ok(content) => toml::from_str::<PgRegistryEntry>(&content).ok(),
+            Err(_) => continue,
+        };
+        let registered = match registered {
+            Some(registered) => registered,
+            None => {
+                warn!("removing invalid registry entry {}", entry_path.display());
+                let _ = tokio::fs::remove_file(&entry_path).await;
+                continue;
+            }
+        };
+        if process_running(registered.pid) {
+            continue;
+        }
+        if let Err(e) = cleanup_orphan(&registered).await {
+            warn!(
+                "could not clean up the orphaned database directory {}: {}",
+                registered.database_dir.display(),
+                e
+            );
+            continue;
+        }
+        let _ = tokio::fs::remove_file(&entry_path).await;
+        removed.push(registered.database_dir);
+    }
+    Ok(removed)
+}
+
+///
+/// Stop the server of an orphaned cluster and remove its files
+///
+async fn cleanup_orphan(registered: &PgRegistryEntry) -> PgResult<()> {
+    if registered.database_dir.join("postmaster.pid").exists() {
+        info!(
+            "stopping orphaned postgresql server of {}",
+            registered.database_dir.display()
+        );
+        // an unclean shutdown is fine, the cluster is removed anyway
+        let _ = tokio::process::Command::new(&registered.pg_ctl)
+            .arg("stop")
+            .arg("-w")
+            .arg("-m")
+            .arg("immediate")
+            .arg("-D")
+            .arg(&registered.database_dir)
+            .output()
+            .await;
+    }
+    let to_clean_up_error = |e: std::io::Error| PgEmbedError {
+        error_type: PgEmbedErrorType::PgCleanUpFailure,
+        source: Some(Box::new(e)),
+        message: Some(format!(
+            "could not remove the orphaned database directory {}",
+            registered.database_dir.display()
+        )),
+    };
+    let removed_dir = registered
+        .temp_dir
+        .as_ref()
+        .unwrap_or(&registered.database_dir);
+    if removed_dir.exists() {
+        tokio::fs::remove_dir_all(removed_dir)
+            .map_err(to_clean_up_error)
+            .await?;
+    }
+    if registered.pw_file.exists() {
+        tokio::fs::remove_file(&registered.pw_file)
+            .map_err(to_clean_up_error)
+            .await?;
+    }
+    Ok(())
+}
+
+///
+/// Check if the process with id `pid` is running
+///
+pub(crate) fn process_running(pid: u32) -> bool {
+    if pid == std::process::id() {
+        return true;
+    }
+    // not a valid process id, `kill` would interpret it as negative process group
+    if pid > i32::MAX as u32 {
+        return false;
+    }
+    #[cfg(unix)]
+    {
+        // processes of other users can't be signaled (EPERM), but they are running
+        (unsafe { libc::kill(pid as libc::pid_t, 0) == 0 })
+            || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+    }
+    #[cfg(windows)]
+    {
+        // `"image name","pid","session name","session#","mem usage"` lines
+        let pid = pid.to_string();
+        std::process::Command::new("tasklist")
+            .arg("/FI")
+            .arg(format!("PID eq {}", pid))
+            .args(["/FO", "CSV", "/NH"])
+            .output()
+            .map(|output| {
+                String::from_utf8_lossy(&output.stdout).lines().any(|line| {
+                    line.split(',')
+                        .nth(1)
+                        .map(|column| column.trim_matches('"'))
+                        == Some(pid.as_str())
+                })
+            })
+            .unwrap_or(true)
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        // without a way to check, never treat a process as orphaned
+        true
+    }
+}
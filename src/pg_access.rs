@@ -2,20 +2,25 @@
 //! Cache postgresql files, access to executables, clean up files
 //!
 
-use std::cell::Cell;
 use std::collections::HashMap;
+use std::ffi::OsString;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use fs2::FileExt;
 use futures::TryFutureExt;
+use tempfile::TempDir;
 use tokio::io::AsyncWriteExt;
 use tokio::sync::Mutex;
 use tokio::time::{interval, Duration};
 
-use crate::pg_enums::{OperationSystem, PgAcquisitionStatus};
+use crate::pg_commands::PgCommand;
+use crate::pg_config::{PgConfig, PG_EMBED_CONFIG_FILE_NAME};
+use crate::pg_enums::PgAcquisitionStatus;
 use crate::pg_errors::{PgEmbedError, PgEmbedErrorType};
-use crate::pg_fetch::PgFetchSettings;
-use crate::pg_types::{PgCommandSync, PgResult};
+use crate::pg_fetch::{PgCacheBudget, PgFetchSettings, PostgresVersion};
+use crate::pg_types::PgResult;
 
 lazy_static! {
     ///
@@ -29,12 +34,65 @@ lazy_static! {
 }
 
 const PG_EMBED_CACHE_DIR_NAME: &'static str = "pg-embed";
+/// Environment variable overriding the cache root directory
+pub const PG_EMBED_CACHE_DIR_ENV: &str = "PG_EMBED_CACHE_DIR";
 const PG_VERSION_FILE_NAME: &'static str = "PG_VERSION";
+const PG_CONFIG_FILE_NAME: &str = "postgresql.conf";
+const PG_EMBED_TEMP_DIR_PREFIX: &str = "pg-embed-";
+const PG_EMBED_CACHE_LOCK_FILE_NAME: &str = ".lock";
+/// Shared lock of the instances using a cached installation, prevents its eviction
+const PG_EMBED_IN_USE_FILE_NAME: &str = ".in-use";
+const PG_EMBED_CACHE_COMPLETE_FILE_NAME: &str = ".complete";
+const PG_EMBED_STAGING_DIR_PREFIX: &str = ".staging-";
+const PG_EMBED_LAST_USE_FILE_NAME: &str = ".last-use";
+const PG_EMBED_TEMPLATES_DIR_NAME: &str = "templates";
+/// Marker of directories created by pg-embed, only those are removed by purge and eviction
+const PG_EMBED_OWNER_FILE_NAME: &str = ".pg-embed-owned";
+/// Entries moved into a cache directory by [PgAccess::commit_staging_dir]
+const PG_EMBED_ENTRIES_FILE_NAME: &str = ".pg-embed-entries";
+/// Executables required in a valid cache
+const PG_REQUIRED_EXECUTABLES: [&str; 5] = ["initdb", "pg_ctl", "postgres", "psql", "pg_dump"];
+
+///
+/// Exclusive advisory lock on a cache directory, released on drop
+///
+/// Prevents simultaneous acquisition of the same binaries by separate processes
+/// (e.g. test binaries run in parallel by `cargo test` or `cargo nextest`).
+///
+pub struct PgCacheLock {
+    file: std::fs::File,
+}
+
+impl Drop for PgCacheLock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
+///
+/// Cached postgresql installation
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgCachedInstallation {
+    /// cache key of the binary provider, e.g. `linux-amd64/13.9.0`
+    pub cache_key: String,
+    /// installation directory
+    pub path: PathBuf,
+    /// disk usage in bytes
+    pub size: u64,
+    /// last time the installation was used by [PgEmbed::setup](crate::postgres::PgEmbed::setup)
+    pub last_used: Option<SystemTime>,
+    /// the installation directory was created by pg-embed,
+    /// user provided directories are never purged or evicted
+    pub owned: bool,
+}
 
 ///
 /// Access to pg_ctl, initdb, database directory and cache directory
 ///
 pub struct PgAccess {
+    /// Cache root directory path, containing the cache directories of all binaries
+    pub cache_root: PathBuf,
     /// Cache directory path
     pub cache_dir: PathBuf,
     /// Database directory path
@@ -43,6 +101,10 @@ pub struct PgAccess {
     pub pg_ctl_exe: PathBuf,
     /// Postgresql initdb executable path
     pub init_db_exe: PathBuf,
+    /// Postgresql server executable path
+    pub postgres_exe: PathBuf,
+    /// Postgresql psql executable path
+    pub psql_exe: PathBuf,
     /// Password file path
     pub pw_file_path: PathBuf,
     /// Postgresql binaries zip file path
@@ -50,35 +112,69 @@ pub struct PgAccess {
     /// Postgresql database version file
     /// used for internal checks
     pg_version_file: PathBuf,
+    /// Temporary directory containing the database directory and the password file
+    /// if no database directory was provided, removed on drop
+    temp_dir: Option<TempDir>,
+    /// Marker file written after the binaries were completely acquired,
+    /// `None` for binaries of an existing installation
+    complete_marker: Option<PathBuf>,
+    /// The cache directory was created by pg-embed
+    owned_cache: bool,
+    /// Shared in-use lock of the cache directory, held for the lifetime of the instance
+    _in_use_lock: Option<PgCacheLock>,
 }
 
 impl PgAccess {
     ///
     /// Create a new instance
     ///
-    /// Directory structure for cached postgresql binaries will be created
+    /// Directory structure for cached postgresql binaries will be created.
+    /// If no database directory is provided, a unique temporary directory will be created
+    /// to hold the database directory.
     ///
     pub async fn new(
         fetch_settings: &PgFetchSettings,
-        database_dir: &PathBuf,
+        database_dir: Option<&PathBuf>,
         cache_dir: Option<&PathBuf>,
     ) -> Result<Self, PgEmbedError> {
-        let cache_dir = match cache_dir {
-            Some(d) => d.clone(),
-            None => Self::create_cache_dir_structure(&fetch_settings).await?,
+        let provider = fetch_settings.binary_provider();
+        let cache_root = Self::cache_root(fetch_settings.cache_dir.as_deref())?;
+        let (cache_dir, owned_cache) = match (cache_dir, provider.installation_dir()) {
+            (Some(d), _) => (d.clone(), is_owned_dir(d)),
+            (None, Some(installation_dir)) => (installation_dir, false),
+            (None, None) => (
+                Self::create_cache_dir_structure(&cache_root, &provider.cache_key()).await?,
+                true,
+            ),
+        };
+        let complete_marker = match provider.installation_dir() {
+            Some(installation_dir) if installation_dir == cache_dir => None,
+            _ => Some(cache_dir.join(PG_EMBED_CACHE_COMPLETE_FILE_NAME)),
+        };
+        let in_use_lock = if owned_cache && complete_marker.is_some() {
+            Some(Self::lock_in_use(&cache_dir).await?)
+        } else {
+            None
         };
 
+        let (database_dir, temp_dir) = match database_dir {
+            Some(d) => (d.clone(), None),
+            None => {
+                let temp_dir = Self::create_temp_dir()?;
+                (temp_dir.path().join("db"), Some(temp_dir))
+            }
+        };
+        let database_dir = &database_dir;
         Self::create_db_dir_structure(database_dir).await?;
-        // pg_ctl executable
-        let mut pg_ctl = cache_dir.clone();
-        pg_ctl.push("bin/pg_ctl");
-        // initdb executable
-        let mut init_db = cache_dir.clone();
-        init_db.push("bin/initdb");
+        // executables
+        let pg_ctl = Self::executable_path(&cache_dir, "pg_ctl");
+        let init_db = Self::executable_path(&cache_dir, "initdb");
+        let postgres = Self::executable_path(&cache_dir, "postgres");
+        let psql = Self::executable_path(&cache_dir, "psql");
         // postgres zip file
         let mut zip_file_path = cache_dir.clone();
         let platform = fetch_settings.platform();
-        let file_name = format!("{}-{}.zip", platform, &fetch_settings.version.0);
+        let file_name = format!("{}-{}.zip", platform, &fetch_settings.version);
         zip_file_path.push(file_name);
         // password file
         let mut pw_file = database_dir.clone();
@@ -88,44 +184,125 @@ impl PgAccess {
         pg_version_file.push(PG_VERSION_FILE_NAME);
 
         Ok(PgAccess {
+            cache_root,
             cache_dir,
             database_dir: database_dir.clone(),
             pg_ctl_exe: pg_ctl,
             init_db_exe: init_db,
+            postgres_exe: postgres,
+            psql_exe: psql,
             pw_file_path: pw_file,
             zip_file_path,
             pg_version_file,
+            temp_dir,
+            complete_marker,
+            owned_cache,
+            _in_use_lock: in_use_lock,
         })
     }
 
+    ///
+    /// Take the shared in-use lock of an owned cache directory
+    ///
+    /// [PgAccess::enforce_cache_budget] only evicts installations it can lock exclusively.
+    /// If the directory was evicted while waiting for the lock, it is created again.
+    ///
+    async fn lock_in_use(cache_dir: &Path) -> PgResult<PgCacheLock> {
+        let cache_dir = cache_dir.to_path_buf();
+        tokio::task::spawn_blocking(move || {
+            let lock_path = cache_dir.join(PG_EMBED_IN_USE_FILE_NAME);
+            let to_lock_error = |e: std::io::Error| PgEmbedError {
+                error_type: PgEmbedErrorType::CacheLockFailure,
+                source: Some(Box::new(e)),
+                message: Some(format!("could not lock {}", lock_path.display())),
+            };
+            loop {
+                std::fs::create_dir_all(&cache_dir).map_err(to_lock_error)?;
+                std::fs::write(cache_dir.join(PG_EMBED_OWNER_FILE_NAME), b"")
+                    .map_err(to_lock_error)?;
+                let file = std::fs::OpenOptions::new()
+                    .create(true)
+                    .truncate(false)
+                    .write(true)
+                    .open(&lock_path)
+                    .map_err(to_lock_error)?;
+                file.lock_shared().map_err(to_lock_error)?;
+                // the lock file is removed together with an evicted installation
+                if lock_path.exists() {
+                    return Ok(PgCacheLock { file });
+                }
+            }
+        })
+        .map_err(|e| PgEmbedError {
+            error_type: PgEmbedErrorType::CacheLockFailure,
+            source: Some(Box::new(e)),
+            message: None,
+        })
+        .await?
+    }
+
+    ///
+    /// Platform specific executable file name (e.g. `pg_ctl.exe` on windows)
+    ///
+    pub fn executable_name(name: &str) -> String {
+        format!("{}{}", name, std::env::consts::EXE_SUFFIX)
+    }
+
+    ///
+    /// Path of an executable in the `bin` directory of a postgresql installation
+    ///
+    pub fn executable_path(installation_dir: &Path, name: &str) -> PathBuf {
+        installation_dir
+            .join("bin")
+            .join(Self::executable_name(name))
+    }
+
+    ///
+    /// Create a unique temporary directory
+    ///
+    fn create_temp_dir() -> PgResult<TempDir> {
+        tempfile::Builder::new()
+            .prefix(PG_EMBED_TEMP_DIR_PREFIX)
+            .tempdir()
+            .map_err(|e| PgEmbedError {
+                error_type: PgEmbedErrorType::DirCreationError,
+                source: Some(Box::new(e)),
+                message: None,
+            })
+    }
+
+    ///
+    /// Check if the database directory is a temporary directory
+    ///
+    /// Temporary database directories are always removed on drop.
+    ///
+    pub fn is_temporary(&self) -> bool {
+        self.temp_dir.is_some()
+    }
+
+    ///
+    /// Temporary directory containing the database directory and the password file
+    ///
+    pub fn temp_dir_path(&self) -> Option<&Path> {
+        self.temp_dir.as_ref().map(TempDir::path)
+    }
+
     ///
     /// Create directory structure for cached postgresql executables
     ///
+    /// The cache root is marked as owned by pg-embed if it is the default cache root
+    /// or didn't exist before, the cache directory is always marked as owned.
+    ///
     /// Returns PathBuf(cache_directory) on success, an error otherwise
     ///
-    async fn create_cache_dir_structure(fetch_settings: &PgFetchSettings) -> PgResult<PathBuf> {
-        let cache_dir = dirs::cache_dir().ok_or_else(|| PgEmbedError {
-            error_type: PgEmbedErrorType::InvalidPgUrl,
-            source: None,
-            message: None,
-        })?;
-        let os_string = match fetch_settings.operating_system {
-            OperationSystem::Darwin | OperationSystem::Windows | OperationSystem::Linux => {
-                fetch_settings.operating_system.to_string()
-            }
-            OperationSystem::AlpineLinux => {
-                format!("arch_{}", fetch_settings.operating_system.to_string())
-            }
-        };
-        let pg_path = format!(
-            "{}/{}/{}/{}",
-            PG_EMBED_CACHE_DIR_NAME,
-            os_string,
-            fetch_settings.architecture.to_string(),
-            fetch_settings.version.0
-        );
-        let mut cache_pg_embed = cache_dir.clone();
-        cache_pg_embed.push(pg_path);
+    async fn create_cache_dir_structure(cache_root: &Path, cache_key: &str) -> PgResult<PathBuf> {
+        let owned_root = !Self::dir_exists(cache_root).await
+            || Self::default_cache_root().ok().as_deref() == Some(cache_root);
+        let mut cache_pg_embed = cache_root.to_path_buf();
+        // cache keys use `/` separators on all platforms
+        for component in cache_key.split('/').filter(|c| !c.is_empty()) {
+            cache_pg_embed.push(component);
+        }
         tokio::fs::create_dir_all(&cache_pg_embed)
             .map_err(|e| PgEmbedError {
                 error_type: PgEmbedErrorType::DirCreationError,
@@ -133,9 +310,36 @@ impl PgAccess {
                 message: None,
             })
             .await?;
+        if owned_root {
+            Self::mark_owned_dir(cache_root).await?;
+        }
+        Self::mark_owned_dir(&cache_pg_embed).await?;
         Ok(cache_pg_embed)
     }
 
+    ///
+    /// Mark a directory as created by pg-embed
+    ///
+    async fn mark_owned_dir(dir: &Path) -> PgResult<()> {
+        tokio::fs::write(dir.join(PG_EMBED_OWNER_FILE_NAME), b"")
+            .map_err(|e| PgEmbedError {
+                error_type: PgEmbedErrorType::WriteFileError,
+                source: Some(Box::new(e)),
+                message: None,
+            })
+            .await
+    }
+
+    ///
+    /// Check if the cache directory was created by pg-embed
+    ///
+    /// Entries of user provided cache directories are only replaced
+    /// if they were acquired by pg-embed.
+    ///
+    pub fn is_owned_cache(&self) -> bool {
+        self.owned_cache
+    }
+
     async fn create_db_dir_structure(db_dir: &PathBuf) -> PgResult<()> {
         tokio::fs::create_dir_all(db_dir)
             .map_err(|e| PgEmbedError {
@@ -149,10 +353,366 @@ impl PgAccess {
     ///
     /// Check if postgresql executables are already cached
     ///
+    /// Cached binaries are only valid if their acquisition completed,
+    /// a killed process may leave a partially populated cache directory.
+    ///
     pub async fn pg_executables_cached(&self) -> PgResult<bool> {
+        if let Some(complete_marker) = &self.complete_marker {
+            if !Self::path_exists(complete_marker).await? {
+                return Ok(false);
+            }
+        }
         Self::path_exists(self.init_db_exe.as_path()).await
     }
 
+    ///
+    /// Verify the integrity of the cached binaries
+    ///
+    /// Checks that the required executables (initdb, pg_ctl, postgres, psql, pg_dump) exist
+    /// and report the major version of `version` with `--version`.
+    ///
+    /// Returns `Ok(())` if the cache is valid, otherwise returns an error describing the problem.
+    ///
+    pub async fn verify_cache(&self, version: &PostgresVersion) -> PgResult<()> {
+        for name in PG_REQUIRED_EXECUTABLES.iter() {
+            let executable = Self::executable_path(&self.cache_dir, name);
+            let invalid = |message: String| PgEmbedError {
+                error_type: PgEmbedErrorType::InvalidPgPackage,
+                source: None,
+                message: Some(message),
+            };
+            PgCommand::check_executable(&executable)?;
+            let output = tokio::process::Command::new(&executable)
+                .arg("--version")
+                .output()
+                .map_err(|e| PgEmbedError {
+                    error_type: PgEmbedErrorType::InvalidPgPackage,
+                    source: Some(Box::new(e)),
+                    message: Some(format!("could not execute {}", executable.display())),
+                })
+                .await?;
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let reported = parse_version_output(&stdout).ok_or_else(|| {
+                invalid(format!(
+                    "unexpected version output of {}: {}",
+                    executable.display(),
+                    stdout.trim()
+                ))
+            })?;
+            let major = reported.split('.').next().unwrap_or_default();
+            if major != version.major().to_string() {
+                return Err(invalid(format!(
+                    "{} reports version {}, expected {}",
+                    executable.display(),
+                    reported,
+                    version
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    ///
+    /// Check if the cached binaries were verified since they last changed
+    ///
+    /// The binaries are considered verified if the required executables exist and
+    /// the `.complete` marker is not older than any of them.
+    /// Binaries without marker (existing installations) are never considered verified.
+    ///
+    pub async fn cache_verified(&self) -> bool {
+        let verified_at = match &self.complete_marker {
+            Some(complete_marker) => match modified_time(complete_marker).await {
+                Some(verified_at) => verified_at,
+                None => return false,
+            },
+            None => return false,
+        };
+        for name in PG_REQUIRED_EXECUTABLES.iter() {
+            let executable = Self::executable_path(&self.cache_dir, name);
+            match modified_time(&executable).await {
+                Some(modified) if modified <= verified_at => {}
+                _ => return false,
+            }
+        }
+        true
+    }
+
+    ///
+    /// Record a successful [PgAccess::verify_cache] by refreshing the `.complete` marker
+    ///
+    pub async fn mark_cache_verified(&self) -> PgResult<()> {
+        if let Some(complete_marker) = &self.complete_marker {
+            tokio::fs::write(complete_marker, b"")
+                .map_err(|e| PgEmbedError {
+                    error_type: PgEmbedErrorType::WriteFileError,
+                    source: Some(Box::new(e)),
+                    message: Some(format!("could not write {}", complete_marker.display())),
+                })
+                .await?;
+        }
+        Ok(())
+    }
+
+    ///
+    /// Invalidate the cached binaries, so they are acquired again
+    ///
+    pub async fn invalidate_cache(&self) -> PgResult<()> {
+        if let Some(complete_marker) = &self.complete_marker {
+            if Self::path_exists(complete_marker).await? {
+                tokio::fs::remove_file(complete_marker)
+                    .map_err(|e| PgEmbedError {
+                        error_type: PgEmbedErrorType::PgCleanUpFailure,
+                        source: Some(Box::new(e)),
+                        message: None,
+                    })
+                    .await?;
+            }
+        }
+        let mut lock = ACQUIRED_PG_BINS.lock().await;
+        lock.remove(&self.cache_dir);
+        Ok(())
+    }
+
+    ///
+    /// Check if the binaries are managed by pg-embed
+    ///
+    /// Binaries of an existing installation are never acquired or repaired.
+    ///
+    pub fn is_managed_cache(&self) -> bool {
+        self.complete_marker.is_some()
+    }
+
+    ///
+    /// Create a staging directory in the cache directory
+    ///
+    /// The binaries are acquired into the staging directory and moved into the cache directory
+    /// by [PgAccess::commit_staging_dir]. The staging directory is removed on drop.
+    ///
+    pub async fn create_staging_dir(&self) -> PgResult<TempDir> {
+        tokio::fs::create_dir_all(&self.cache_dir)
+            .map_err(|e| PgEmbedError {
+                error_type: PgEmbedErrorType::DirCreationError,
+                source: Some(Box::new(e)),
+                message: None,
+            })
+            .await?;
+        tempfile::Builder::new()
+            .prefix(PG_EMBED_STAGING_DIR_PREFIX)
+            .tempdir_in(&self.cache_dir)
+            .map_err(|e| PgEmbedError {
+                error_type: PgEmbedErrorType::DirCreationError,
+                source: Some(Box::new(e)),
+                message: None,
+            })
+    }
+
+    ///
+    /// Move the acquired binaries from the staging directory into the cache directory
+    ///
+    /// Every entry is renamed into place, replacing leftovers of an incomplete acquisition.
+    /// The completion marker is written last, so the cache is only valid once all entries moved.
+    ///
+    pub async fn commit_staging_dir(&self, staging_dir: TempDir) -> PgResult<()> {
+        let to_move_error = |e: std::io::Error| PgEmbedError {
+            error_type: PgEmbedErrorType::WriteFileError,
+            source: Some(Box::new(e)),
+            message: Some(format!(
+                "could not move the binaries into {}",
+                self.cache_dir.display()
+            )),
+        };
+        let entries_path = self.cache_dir.join(PG_EMBED_ENTRIES_FILE_NAME);
+        let mut acquired_entries: Vec<String> = tokio::fs::read_to_string(&entries_path)
+            .await
+            .map(|entries| entries.lines().map(str::to_string).collect())
+            .unwrap_or_default();
+        let mut entries = tokio::fs::read_dir(staging_dir.path())
+            .map_err(to_move_error)
+            .await?;
+        while let Some(entry) = entries.next_entry().map_err(to_move_error).await? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let target = self.cache_dir.join(entry.file_name());
+            let metadata = tokio::fs::symlink_metadata(&target).await;
+            // never replace contents of a user provided cache directory pg-embed didn't create
+            if metadata.is_ok() && !self.owned_cache && !acquired_entries.contains(&name) {
+                return Err(PgEmbedError {
+                    error_type: PgEmbedErrorType::WriteFileError,
+                    source: None,
+                    message: Some(format!(
+                        "refusing to replace {}, it wasn't created by pg-embed",
+                        target.display()
+                    )),
+                });
+            }
+            match metadata {
+                Ok(metadata) if metadata.is_dir() => {
+                    tokio::fs::remove_dir_all(&target)
+                        .map_err(to_move_error)
+                        .await?
+                }
+                Ok(_) => {
+                    tokio::fs::remove_file(&target)
+                        .map_err(to_move_error)
+                        .await?
+                }
+                Err(_) => {}
+            }
+            tokio::fs::rename(entry.path(), &target)
+                .map_err(to_move_error)
+                .await?;
+            if !acquired_entries.contains(&name) {
+                acquired_entries.push(name);
+            }
+        }
+        tokio::fs::write(&entries_path, acquired_entries.join("\n"))
+            .map_err(to_move_error)
+            .await?;
+        if let Some(complete_marker) = &self.complete_marker {
+            tokio::fs::write(complete_marker, b"")
+                .map_err(to_move_error)
+                .await?;
+        }
+        Ok(())
+    }
+
+    ///
+    /// Directory of the template cluster identified by `template_key`
+    ///
+    pub fn template_dir(&self, template_key: &str) -> PathBuf {
+        self.cache_root
+            .join(PG_EMBED_TEMPLATES_DIR_NAME)
+            .join(template_key)
+    }
+
+    ///
+    /// Create a staging directory for the initialization of a template cluster
+    ///
+    /// The staging directory is removed on drop.
+    ///
+    pub async fn create_template_staging_dir(&self) -> PgResult<TempDir> {
+        let templates_dir = self.cache_root.join(PG_EMBED_TEMPLATES_DIR_NAME);
+        tokio::fs::create_dir_all(&templates_dir)
+            .map_err(|e| PgEmbedError {
+                error_type: PgEmbedErrorType::DirCreationError,
+                source: Some(Box::new(e)),
+                message: None,
+            })
+            .await?;
+        tempfile::Builder::new()
+            .prefix(PG_EMBED_STAGING_DIR_PREFIX)
+            .tempdir_in(&templates_dir)
+            .map_err(|e| PgEmbedError {
+                error_type: PgEmbedErrorType::DirCreationError,
+                source: Some(Box::new(e)),
+                message: None,
+            })
+    }
+
+    ///
+    /// Move an initialized cluster into place as template cluster
+    ///
+    /// If another process committed the same template meanwhile, its template is kept.
+    ///
+    pub async fn commit_template(&self, cluster_dir: &Path, template_dir: &Path) -> PgResult<()> {
+        match tokio::fs::rename(cluster_dir, template_dir).await {
+            Ok(()) => Ok(()),
+            Err(_) if Self::pg_version_file_exists(&template_dir.to_path_buf()).await? => Ok(()),
+            Err(e) => Err(PgEmbedError {
+                error_type: PgEmbedErrorType::WriteFileError,
+                source: Some(Box::new(e)),
+                message: Some(format!(
+                    "could not move the template cluster into {}",
+                    template_dir.display()
+                )),
+            }),
+        }
+    }
+
+    ///
+    /// Copy a database cluster into the database directory
+    ///
+    /// The files are cloned copy-on-write on filesystems supporting reflinks
+    /// (APFS, Btrfs, XFS), falling back to a plain copy. The database directory must be empty.
+    ///
+    pub async fn copy_cluster(&self, source_dir: &Path) -> PgResult<()> {
+        clone_dir(source_dir, &self.database_dir).await
+    }
+
+    ///
+    /// Snapshot directory `name` of the database cluster
+    ///
+    /// Snapshots are stored next to the database directory
+    /// (`{database_dir}.snapshots/{name}`), so snapshots of temporary clusters are removed
+    /// together with them.
+    ///
+    pub fn snapshot_dir(&self, name: &str) -> PathBuf {
+        sibling_path(&self.database_dir, ".snapshots").join(name)
+    }
+
+    ///
+    /// Copy the database cluster into the snapshot directory `name`
+    ///
+    /// An existing snapshot of the same name is replaced. The server must be stopped.
+    ///
+    pub async fn snapshot_cluster(&self, name: &str) -> PgResult<()> {
+        let snapshot_dir = self.snapshot_dir(name);
+        if snapshot_dir.exists() {
+            tokio::fs::remove_dir_all(&snapshot_dir)
+                .map_err(|e| PgEmbedError {
+                    error_type: PgEmbedErrorType::PgCleanUpFailure,
+                    source: Some(Box::new(e)),
+                    message: Some(format!(
+                        "could not remove snapshot {}",
+                        snapshot_dir.display()
+                    )),
+                })
+                .await?;
+        }
+        tokio::fs::create_dir_all(&snapshot_dir)
+            .map_err(|e| PgEmbedError {
+                error_type: PgEmbedErrorType::DirCreationError,
+                source: Some(Box::new(e)),
+                message: None,
+            })
+            .await?;
+        clone_dir(&self.database_dir, &snapshot_dir).await
+    }
+
+    ///
+    /// Replace the database cluster with the snapshot `name`
+    ///
+    /// The server must be stopped.
+    ///
+    pub async fn restore_cluster_snapshot(&self, name: &str) -> PgResult<()> {
+        let snapshot_dir = self.snapshot_dir(name);
+        if !snapshot_dir.is_dir() {
+            return Err(PgEmbedError {
+                error_type: PgEmbedErrorType::ReadFileError,
+                source: None,
+                message: Some(format!(
+                    "no snapshot {} in {}",
+                    name,
+                    snapshot_dir.display()
+                )),
+            });
+        }
+        tokio::fs::remove_dir_all(&self.database_dir)
+            .map_err(|e| PgEmbedError {
+                error_type: PgEmbedErrorType::PgCleanUpFailure,
+                source: Some(Box::new(e)),
+                message: None,
+            })
+            .await?;
+        tokio::fs::create_dir_all(&self.database_dir)
+            .map_err(|e| PgEmbedError {
+                error_type: PgEmbedErrorType::DirCreationError,
+                source: Some(Box::new(e)),
+                message: None,
+            })
+            .await?;
+        clone_dir(&snapshot_dir, &self.database_dir).await
+    }
+
     ///
     /// Check if database files exist
     ///
@@ -160,6 +720,32 @@ impl PgAccess {
         Self::path_exists(self.pg_version_file.as_path()).await
     }
 
+    ///
+    /// Check if the database directory contains no files
+    ///
+    pub async fn db_dir_is_empty(&self) -> PgResult<bool> {
+        let mut entries = match tokio::fs::read_dir(&self.database_dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(true),
+            Err(e) => {
+                return Err(PgEmbedError {
+                    error_type: PgEmbedErrorType::ReadFileError,
+                    source: Some(Box::new(e)),
+                    message: None,
+                })
+            }
+        };
+        entries
+            .next_entry()
+            .map_ok(|entry| entry.is_none())
+            .map_err(|e| PgEmbedError {
+                error_type: PgEmbedErrorType::ReadFileError,
+                source: Some(Box::new(e)),
+                message: None,
+            })
+            .await
+    }
+
     ///
     /// Check if database version file exists
     ///
@@ -177,6 +763,8 @@ impl PgAccess {
     ///
     /// Check if file path exists
     ///
+    /// Only used for files, opening a directory fails on windows, see [PgAccess::dir_exists].
+    ///
     async fn path_exists(file: &Path) -> PgResult<bool> {
         if let Ok(_) = tokio::fs::File::open(file).await {
             Ok(true)
@@ -185,6 +773,13 @@ impl PgAccess {
         }
     }
 
+    ///
+    /// Check if directory path exists
+    ///
+    async fn dir_exists(dir: &Path) -> bool {
+        tokio::fs::metadata(dir).await.is_ok()
+    }
+
     ///
     /// Mark postgresql binaries acquisition in progress
     ///
@@ -209,6 +804,18 @@ impl PgAccess {
         Ok(())
     }
 
+    ///
+    /// Mark postgresql binaries acquisition failed
+    ///
+    /// Used when acquiring postgresql has failed, so that waiting instances
+    /// of PgEmbed retry the acquisition instead of waiting for the binaries
+    ///
+    pub async fn mark_acquisition_failed(&self) -> PgResult<()> {
+        let mut lock = ACQUIRED_PG_BINS.lock().await;
+        lock.insert(self.cache_dir.clone(), PgAcquisitionStatus::Failed);
+        Ok(())
+    }
+
     ///
     /// Check postgresql acquisition status
     ///
@@ -221,9 +828,94 @@ impl PgAccess {
         }
     }
 
+    ///
+    /// Lock the cache directory across processes
+    ///
+    /// Waits until the lock held by another process or instance is released.
+    ///
+    /// Returns the lock guard on success, otherwise returns an error.
+    ///
+    pub async fn lock_cache(&self) -> PgResult<PgCacheLock> {
+        tokio::fs::create_dir_all(&self.cache_dir)
+            .map_err(|e| PgEmbedError {
+                error_type: PgEmbedErrorType::DirCreationError,
+                source: Some(Box::new(e)),
+                message: None,
+            })
+            .await?;
+        let lock_path = self.cache_dir.join(PG_EMBED_CACHE_LOCK_FILE_NAME);
+        tokio::task::spawn_blocking(move || {
+            let to_lock_error = |e: std::io::Error| PgEmbedError {
+                error_type: PgEmbedErrorType::CacheLockFailure,
+                source: Some(Box::new(e)),
+                message: Some(format!("could not lock {}", lock_path.display())),
+            };
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(&lock_path)
+                .map_err(to_lock_error)?;
+            file.lock_exclusive().map_err(to_lock_error)?;
+            Ok(PgCacheLock { file })
+        })
+        .map_err(|e| PgEmbedError {
+            error_type: PgEmbedErrorType::CacheLockFailure,
+            source: Some(Box::new(e)),
+            message: None,
+        })
+        .await?
+    }
+
+    ///
+    /// Lock the database directory across processes
+    ///
+    /// Serializes the initialization of the same database directory by concurrent
+    /// instances, waiting until the lock held by another process or instance is released.
+    /// The lock file is placed next to the database directory (`{database_dir}.lock`).
+    ///
+    /// Returns the lock guard on success, otherwise returns an error.
+    ///
+    pub async fn lock_database_dir(&self) -> PgResult<PgCacheLock> {
+        let lock_path = sibling_path(&self.database_dir, ".lock");
+        if let Some(parent) = lock_path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .map_err(|e| PgEmbedError {
+                    error_type: PgEmbedErrorType::DirCreationError,
+                    source: Some(Box::new(e)),
+                    message: None,
+                })
+                .await?;
+        }
+        tokio::task::spawn_blocking(move || {
+            let to_lock_error = |e: std::io::Error| PgEmbedError {
+                error_type: PgEmbedErrorType::PgLockError,
+                source: Some(Box::new(e)),
+                message: Some(format!("could not lock {}", lock_path.display())),
+            };
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(&lock_path)
+                .map_err(to_lock_error)?;
+            file.lock_exclusive().map_err(to_lock_error)?;
+            Ok(PgCacheLock { file })
+        })
+        .map_err(|e| PgEmbedError {
+            error_type: PgEmbedErrorType::PgLockError,
+            source: Some(Box::new(e)),
+            message: None,
+        })
+        .await?
+    }
+
     ///
     /// Determine if postgresql binaries acquisition is needed
     ///
+    /// Waits for the acquisition of another instance. If it failed, acquisition is needed,
+    /// callers serialize the retry with [PgAccess::lock_cache].
+    ///
     pub async fn acquisition_needed(&self) -> PgResult<bool> {
         if !self.pg_executables_cached().await? {
             match self.acquisition_status().await {
@@ -232,41 +924,21 @@ impl PgAccess {
                     while self.acquisition_status().await == PgAcquisitionStatus::InProgress {
                         interval.tick().await;
                     }
-                    Ok(false)
+                    Ok(self.acquisition_status().await == PgAcquisitionStatus::Failed)
                 }
                 PgAcquisitionStatus::Finished => Ok(false),
-                PgAcquisitionStatus::Undefined => Ok(true),
+                PgAcquisitionStatus::Failed | PgAcquisitionStatus::Undefined => Ok(true),
             }
         } else {
             Ok(false)
         }
     }
 
-    ///
-    /// Write pg binaries zip to postgresql cache directory
-    ///
-    pub async fn write_pg_zip(&self, bytes: &[u8]) -> PgResult<()> {
-        let mut file: tokio::fs::File = tokio::fs::File::create(&self.zip_file_path.as_path())
-            .map_err(|e| PgEmbedError {
-                error_type: PgEmbedErrorType::WriteFileError,
-                source: Some(Box::new(e)),
-                message: None,
-            })
-            .await?;
-        file.write_all(&bytes)
-            .map_err(|e| PgEmbedError {
-                error_type: PgEmbedErrorType::WriteFileError,
-                source: Some(Box::new(e)),
-                message: None,
-            })
-            .await?;
-        Ok(())
-    }
-
     ///
     /// Clean up created files and directories.
     ///
-    /// Remove created directories containing the database and the password file.
+    /// Remove created directories containing the database, its snapshots, its lock file
+    /// and the password file.
     ///
     pub fn clean(&self) -> PgResult<()> {
         // not using tokio::fs async methods because clean() is called on drop
@@ -275,6 +947,15 @@ impl PgAccess {
             source: Some(Box::new(e)),
             message: None,
         })?;
+        let snapshots_dir = sibling_path(&self.database_dir, ".snapshots");
+        if snapshots_dir.exists() {
+            std::fs::remove_dir_all(snapshots_dir).map_err(|e| PgEmbedError {
+                error_type: PgEmbedErrorType::PgCleanUpFailure,
+                source: Some(Box::new(e)),
+                message: None,
+            })?;
+        }
+        let _ = std::fs::remove_file(sibling_path(&self.database_dir, ".lock"));
         std::fs::remove_file(self.pw_file_path.as_path()).map_err(|e| PgEmbedError {
             error_type: PgEmbedErrorType::PgCleanUpFailure,
             source: Some(Box::new(e)),
@@ -288,25 +969,197 @@ impl PgAccess {
     ///
     /// Remove all cached postgresql executables
     ///
-    pub async fn purge() -> PgResult<()> {
-        let mut cache_dir = dirs::cache_dir().ok_or_else(|| PgEmbedError {
-            error_type: PgEmbedErrorType::ReadFileError,
-            source: None,
-            message: Some(String::from("cache dir error")),
-        })?;
-        cache_dir.push(PG_EMBED_CACHE_DIR_NAME);
-        let _ = tokio::fs::remove_dir_all(cache_dir.as_path())
+    /// The cache root is resolved like [PgAccess::new] does, honouring
+    /// [PgFetchSettings::cache_dir].
+    /// A user provided cache root is kept, only the installations created by pg-embed are removed.
+    ///
+    pub async fn purge(fetch_settings: &PgFetchSettings) -> PgResult<()> {
+        let cache_dir = Self::cache_root(fetch_settings.cache_dir.as_deref())?;
+        if is_owned_dir(&cache_dir) || Self::default_cache_root()? == cache_dir {
+            let _ = tokio::fs::remove_dir_all(cache_dir.as_path())
+                .map_err(|e| PgEmbedError {
+                    error_type: PgEmbedErrorType::PgPurgeFailure,
+                    source: Some(Box::new(e)),
+                    message: None,
+                })
+                .await;
+            return Ok(());
+        }
+        for installation in Self::list_cached_in(cache_dir).await? {
+            if installation.owned {
+                let _ = tokio::fs::remove_dir_all(&installation.path).await;
+            }
+        }
+        Ok(())
+    }
+
+    ///
+    /// Purge the cached postgresql executables of a single version
+    ///
+    /// `platform` is the platform string of the fetch settings,
+    /// see [PgFetchSettings::platform], e.g. `linux-amd64`.
+    ///
+    pub async fn purge_version(
+        fetch_settings: &PgFetchSettings,
+        version: &PostgresVersion,
+        platform: &str,
+    ) -> PgResult<()> {
+        let installation_dir = Self::cache_root(fetch_settings.cache_dir.as_deref())?
+            .join(platform)
+            .join(version.to_string());
+        if !Self::dir_exists(&installation_dir).await {
+            return Ok(());
+        }
+        if !is_owned_dir(&installation_dir) {
+            return Err(PgEmbedError {
+                error_type: PgEmbedErrorType::PgPurgeFailure,
+                source: None,
+                message: Some(format!(
+                    "{} wasn't created by pg-embed",
+                    installation_dir.display()
+                )),
+            });
+        }
+        tokio::fs::remove_dir_all(&installation_dir)
             .map_err(|e| PgEmbedError {
                 error_type: PgEmbedErrorType::PgPurgeFailure,
                 source: Some(Box::new(e)),
                 message: None,
             })
-            .await;
-        Ok(())
+            .await
+    }
+
+    ///
+    /// List the cached postgresql installations with their disk usage
+    ///
+    /// The cache root is resolved like [PgAccess::new] does, honouring
+    /// [PgFetchSettings::cache_dir].
+    ///
+    pub async fn list_cached(
+        fetch_settings: &PgFetchSettings,
+    ) -> PgResult<Vec<PgCachedInstallation>> {
+        Self::list_cached_in(Self::cache_root(fetch_settings.cache_dir.as_deref())?).await
+    }
+
+    ///
+    /// List the cached postgresql installations below `cache_root`
+    ///
+    async fn list_cached_in(cache_root: PathBuf) -> PgResult<Vec<PgCachedInstallation>> {
+        tokio::task::spawn_blocking(move || {
+            let mut installations = Vec::new();
+            find_installations(&cache_root, &cache_root, &mut installations);
+            installations.sort_by(|a, b| a.cache_key.cmp(&b.cache_key));
+            installations
+        })
+        .map_err(|e| PgEmbedError {
+            error_type: PgEmbedErrorType::ReadFileError,
+            source: Some(Box::new(e)),
+            message: None,
+        })
+        .await
+    }
+
+    ///
+    /// Record the current time as last use of the cached binaries
+    ///
+    /// Used to evict the least recently used installations, see [PgAccess::enforce_cache_budget].
+    ///
+    pub async fn touch_last_use(&self) -> PgResult<()> {
+        if !self.is_managed_cache() {
+            return Ok(());
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        tokio::fs::write(
+            self.cache_dir.join(PG_EMBED_LAST_USE_FILE_NAME),
+            now.to_string(),
+        )
+        .map_err(|e| PgEmbedError {
+            error_type: PgEmbedErrorType::WriteFileError,
+            source: Some(Box::new(e)),
+            message: None,
+        })
+        .await
+    }
+
+    ///
+    /// Evict the least recently used cached installations exceeding `budget`
+    ///
+    /// Installations used by a living instance of this or another process (see the in-use
+    /// lock taken by [PgAccess::new]) and installations being acquired are never evicted.
+    ///
+    /// Returns the evicted installations on success, otherwise returns an error.
+    ///
+    pub async fn enforce_cache_budget(
+        &self,
+        budget: &PgCacheBudget,
+    ) -> PgResult<Vec<PgCachedInstallation>> {
+        let mut installations = Self::list_cached_in(self.cache_root.clone()).await?;
+        // least recently used first, installations without a recorded use are the oldest
+        installations.sort_by_key(|installation| installation.last_used);
+        let mut count = installations.len();
+        let mut size: u64 = installations.iter().map(|i| i.size).sum();
+        let mut evicted = Vec::new();
+        for installation in installations {
+            let over_count = matches!(budget.max_installations, Some(max) if count > max);
+            let over_size = matches!(budget.max_size, Some(max) if size > max);
+            if !over_count && !over_size {
+                break;
+            }
+            if installation.path == self.cache_dir || !installation.owned {
+                continue;
+            }
+            let path = installation.path.clone();
+            let removed = tokio::task::spawn_blocking(move || evict_installation(&path))
+                .map_err(|e| PgEmbedError {
+                    error_type: PgEmbedErrorType::PgPurgeFailure,
+                    source: Some(Box::new(e)),
+                    message: None,
+                })
+                .await??;
+            if removed {
+                count -= 1;
+                size = size.saturating_sub(installation.size);
+                evicted.push(installation);
+            }
+        }
+        Ok(evicted)
+    }
+
+    ///
+    /// The pg-embed cache root directory
+    ///
+    /// Resolved from `cache_dir` if set, then the `PG_EMBED_CACHE_DIR` environment variable,
+    /// falling back to `pg-embed` in the user cache directory.
+    ///
+    pub fn cache_root(cache_dir: Option<&Path>) -> PgResult<PathBuf> {
+        if let Some(cache_dir) = cache_dir {
+            return Ok(cache_dir.to_path_buf());
+        }
+        if let Some(cache_dir) = std::env::var_os(PG_EMBED_CACHE_DIR_ENV).filter(|d| !d.is_empty())
+        {
+            return Ok(PathBuf::from(cache_dir));
+        }
+        Self::default_cache_root()
+    }
+
+    ///
+    /// The default cache root directory, `pg-embed` in the user cache directory
+    ///
+    fn default_cache_root() -> PgResult<PathBuf> {
+        dirs::cache_dir()
+            .map(|cache_dir| cache_dir.join(PG_EMBED_CACHE_DIR_NAME))
+            .ok_or_else(|| PgEmbedError {
+                error_type: PgEmbedErrorType::ReadFileError,
+                source: None,
+                message: Some(String::from("cache dir error")),
+            })
     }
 
     ///
-    /// Clean up database directory and password file
+    /// Clean up database directory, its snapshots and password file
     ///
     pub async fn clean_up(database_dir: PathBuf, pw_file: PathBuf) -> PgResult<()> {
         tokio::fs::remove_dir_all(database_dir.as_path())
@@ -317,6 +1170,18 @@ impl PgAccess {
                 message: None,
             })?;
 
+        let snapshots_dir = sibling_path(&database_dir, ".snapshots");
+        if snapshots_dir.exists() {
+            tokio::fs::remove_dir_all(snapshots_dir.as_path())
+                .await
+                .map_err(|e| PgEmbedError {
+                    error_type: PgEmbedErrorType::PgCleanUpFailure,
+                    source: Some(Box::new(e)),
+                    message: None,
+                })?;
+        }
+        let _ = tokio::fs::remove_file(sibling_path(&database_dir, ".lock")).await;
+
         tokio::fs::remove_file(pw_file.as_path())
             .await
             .map_err(|e| PgEmbedError {
@@ -332,33 +1197,342 @@ impl PgAccess {
     /// Returns `Ok(())` on success, otherwise returns an error.
     ///
     pub async fn create_password_file(&self, password: &[u8]) -> PgResult<()> {
+        let to_auth_error = |e: std::io::Error| PgEmbedError {
+            error_type: PgEmbedErrorType::AuthSetupFailed,
+            source: Some(Box::new(e)),
+            message: Some(format!(
+                "could not write the password file {}",
+                self.pw_file_path.display()
+            )),
+        };
         let mut file: tokio::fs::File = tokio::fs::File::create(self.pw_file_path.as_path())
+            .map_err(to_auth_error)
+            .await?;
+        file.write_all(password).map_err(to_auth_error).await?;
+        file.flush().map_err(to_auth_error).await?;
+        Ok(())
+    }
+
+    ///
+    /// Write the server configuration
+    ///
+    /// Writes `pg_embed.conf` to the database directory and includes it in `postgresql.conf`.
+    /// Must be called after the database cluster was initialized.
+    ///
+    /// Returns `Ok(())` on success, otherwise returns an error.
+    ///
+    pub async fn write_config(&self, config: &PgConfig) -> PgResult<()> {
+        let mut config_file = self.database_dir.clone();
+        config_file.push(PG_EMBED_CONFIG_FILE_NAME);
+        tokio::fs::write(&config_file, config.to_config_file())
             .map_err(|e| PgEmbedError {
                 error_type: PgEmbedErrorType::WriteFileError,
                 source: Some(Box::new(e)),
                 message: None,
             })
             .await?;
-        let _ = file
-            .write(password)
+
+        let mut pg_config_file = self.database_dir.clone();
+        pg_config_file.push(PG_CONFIG_FILE_NAME);
+        let pg_config = tokio::fs::read_to_string(&pg_config_file)
             .map_err(|e| PgEmbedError {
-                error_type: PgEmbedErrorType::WriteFileError,
+                error_type: PgEmbedErrorType::ReadFileError,
                 source: Some(Box::new(e)),
                 message: None,
             })
             .await?;
+        let include = format!("include_if_exists = '{}'", PG_EMBED_CONFIG_FILE_NAME);
+        if !pg_config.lines().any(|line| line.trim() == include) {
+            let mut file = tokio::fs::OpenOptions::new()
+                .append(true)
+                .open(&pg_config_file)
+                .map_err(|e| PgEmbedError {
+                    error_type: PgEmbedErrorType::WriteFileError,
+                    source: Some(Box::new(e)),
+                    message: None,
+                })
+                .await?;
+            file.write_all(format!("\n{}\n", include).as_bytes())
+                .map_err(|e| PgEmbedError {
+                    error_type: PgEmbedErrorType::WriteFileError,
+                    source: Some(Box::new(e)),
+                    message: None,
+                })
+                .await?;
+        }
         Ok(())
     }
 
     ///
     /// Create synchronous pg_ctl stop command
     ///
-    pub fn stop_db_command_sync(&self, database_dir: &PathBuf) -> PgCommandSync {
-        let pg_ctl_executable = self.pg_ctl_exe.to_str().unwrap();
-        let mut command = Box::new(Cell::new(std::process::Command::new(pg_ctl_executable)));
+    pub fn stop_db_command_sync(
+        &self,
+        database_dir: &PathBuf,
+        envs: &HashMap<OsString, OsString>,
+    ) -> std::process::Command {
+        let mut command = std::process::Command::new(&self.pg_ctl_exe);
         command
-            .get_mut()
-            .args(&["stop", "-w", "-D", database_dir.to_str().unwrap()]);
+            .args(["stop", "-w", "-D"])
+            .arg(database_dir)
+            .env_clear()
+            .envs(envs);
         command
     }
 }
+
+///
+/// Parse the version reported by `<executable> --version`
+///
+/// e.g. `13.9` of `initdb (PostgreSQL) 13.9 (Debian 13.9-1.pgdg110+1)`
+///
+pub(crate) fn parse_version_output(output: &str) -> Option<String> {
+    output
+        .split_whitespace()
+        .skip_while(|token| *token != "(PostgreSQL)")
+        .nth(1)
+        .map(|version| version.to_string())
+}
+
+///
+/// Path next to the database directory, `{database_dir}{suffix}`
+///
+fn sibling_path(database_dir: &Path, suffix: &str) -> PathBuf {
+    let mut path = database_dir.as_os_str().to_os_string();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+///
+/// Modification time of `path`, `None` if it does not exist
+///
+async fn modified_time(path: &Path) -> Option<SystemTime> {
+    tokio::fs::metadata(path)
+        .await
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+///
+/// Collect the installations (directories containing `bin/`) below `dir`
+///
+fn find_installations(root: &Path, dir: &Path, installations: &mut Vec<PgCachedInstallation>) {
+    if dir.join("bin").is_dir() {
+        let cache_key = dir
+            .strip_prefix(root)
+            .map(|key| {
+                key.components()
+                    .map(|c| c.as_os_str().to_string_lossy().to_string())
+                    .collect::<Vec<String>>()
+                    .join("/")
+            })
+            .unwrap_or_default();
+        let last_used = std::fs::read_to_string(dir.join(PG_EMBED_LAST_USE_FILE_NAME))
+            .ok()
+            .and_then(|secs| secs.trim().parse::<u64>().ok())
+            .map(|secs| UNIX_EPOCH + Duration::from_secs(secs));
+        installations.push(PgCachedInstallation {
+            cache_key,
+            path: dir.to_path_buf(),
+            size: dir_size(dir),
+            last_used,
+            owned: is_owned_dir(dir),
+        });
+        return;
+    }
+    if let Ok(entries) = std::fs::read_dir(dir) {
+        for entry in entries.filter_map(Result::ok) {
+            if entry.file_type().map(|t| t.is_dir()).unwrap_or(false) {
+                find_installations(root, &entry.path(), installations);
+            }
+        }
+    }
+}
+
+///
+/// Disk usage of a directory in bytes, symbolic links are not followed
+///
+fn dir_size(dir: &Path) -> u64 {
+    std::fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .map(|entry| match entry.metadata() {
+                    Ok(metadata) if metadata.is_dir() => dir_size(&entry.path()),
+                    Ok(metadata) => metadata.len(),
+                    Err(_) => 0,
+                })
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+///
+/// Remove a cached installation unless it is in use or being acquired
+///
+/// Returns `Ok(true)` if the installation was removed, `Ok(false)` if it is locked.
+///
+fn evict_installation(installation_dir: &Path) -> PgResult<bool> {
+    let try_lock = |name: &str| -> PgResult<Option<std::fs::File>> {
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(installation_dir.join(name))
+            .map_err(|e| PgEmbedError {
+                error_type: PgEmbedErrorType::CacheLockFailure,
+                source: Some(Box::new(e)),
+                message: None,
+            })?;
+        Ok(file.try_lock_exclusive().ok().map(|_| file))
+    };
+    let lock_file = match try_lock(PG_EMBED_CACHE_LOCK_FILE_NAME)? {
+        Some(file) => file,
+        None => return Ok(false),
+    };
+    let in_use_file = match try_lock(PG_EMBED_IN_USE_FILE_NAME)? {
+        Some(file) => file,
+        None => {
+            let _ = lock_file.unlock();
+            return Ok(false);
+        }
+    };
+    let removed = std::fs::remove_dir_all(installation_dir).map_err(|e| PgEmbedError {
+        error_type: PgEmbedErrorType::PgPurgeFailure,
+        source: Some(Box::new(e)),
+        message: Some(format!(
+            "could not evict {} from the cache",
+            installation_dir.display()
+        )),
+    });
+    let _ = in_use_file.unlock();
+    let _ = lock_file.unlock();
+    removed.map(|_| true)
+}
+
+///
+/// Copy the contents of `source` into the empty directory `target`
+///
+/// The files are cloned copy-on-write on filesystems supporting reflinks, falling back to
+/// a plain copy.
+///
+async fn clone_dir(source: &Path, target: &Path) -> PgResult<()> {
+    let source = source.to_path_buf();
+    let target = target.to_path_buf();
+    let target_display = target.display().to_string();
+    tokio::task::spawn_blocking(move || {
+        if reflink_dir(&source, &target) {
+            return Ok(());
+        }
+        // remove the files of a partial clone before copying
+        if target.exists() {
+            std::fs::remove_dir_all(&target)?;
+        }
+        copy_dir_all(&source, &target)
+    })
+    .map_err(|e| PgEmbedError {
+        error_type: PgEmbedErrorType::PgTaskJoinError,
+        source: Some(Box::new(e)),
+        message: None,
+    })
+    .await?
+    .map_err(|e| PgEmbedError {
+        error_type: PgEmbedErrorType::WriteFileError,
+        source: Some(Box::new(e)),
+        message: Some(format!(
+            "could not copy the database cluster into {}",
+            target_display
+        )),
+    })
+}
+
+///
+/// Recursively copy the contents of `source` into `target`, keeping the permissions
+///
+fn copy_dir_all(source: &Path, target: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(target)?;
+    std::fs::set_permissions(target, std::fs::metadata(source)?.permissions())?;
+    for entry in std::fs::read_dir(source)? {
+        let entry = entry?;
+        let target_path = target.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        // tablespaces are linked from `pg_tblspc`, the links are kept instead of followed
+        if file_type.is_symlink() {
+            copy_symlink(&entry.path(), &target_path)?;
+        } else if file_type.is_dir() {
+            copy_dir_all(&entry.path(), &target_path)?;
+        } else {
+            std::fs::copy(entry.path(), &target_path)?;
+        }
+    }
+    Ok(())
+}
+
+///
+/// Create a link at `target` pointing to the destination of the link `source`
+///
+fn copy_symlink(source: &Path, target: &Path) -> std::io::Result<()> {
+    let destination = std::fs::read_link(source)?;
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(destination, target)
+    }
+    #[cfg(windows)]
+    {
+        if source.is_dir() {
+            std::os::windows::fs::symlink_dir(destination, target)
+        } else {
+            std::os::windows::fs::symlink_file(destination, target)
+        }
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        let _ = (destination, target);
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "symbolic links are not supported",
+        ))
+    }
+}
+
+///
+/// Clone the contents of `source` into `target` with reflinks
+///
+/// Returns `true` if all files were cloned, `false` if reflinks are not supported.
+///
+fn reflink_dir(source: &Path, target: &Path) -> bool {
+    #[cfg(target_os = "linux")]
+    {
+        std::process::Command::new("cp")
+            .arg("-a")
+            .arg("--reflink=always")
+            .arg(source.join("."))
+            .arg(target)
+            .output()
+            .map(|output| output.status.success())
+            .unwrap_or(false)
+    }
+    #[cfg(target_os = "macos")]
+    {
+        std::process::Command::new("cp")
+            .arg("-Rpc")
+            .arg(source.join("."))
+            .arg(target)
+            .output()
+            .map(|output| output.status.success())
+            .unwrap_or(false)
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    {
+        let _ = (source, target);
+        false
+    }
+}
+
+///
+/// Check if a directory was created by pg-embed
+///
+fn is_owned_dir(dir: &Path) -> bool {
+    dir.join(PG_EMBED_OWNER_FILE_NAME).exists()
+}
@@ -0,0 +1,82 @@
+//!
+//! Fake clock
+//!
+//! Shift the time returned by `now()` and the related functions, so time dependent sql
+//! (partitions, TTLs) can be tested deterministically.
+//!
+//! [PgEmbed::install_fake_clock] creates `now()`, `transaction_timestamp()`,
+//! `statement_timestamp()` and `clock_timestamp()` overrides in the `pg_embed_clock` schema
+//! and puts the schema in front of `pg_catalog` in the database's `search_path`. The
+//! overrides add the offset in seconds of the `pg_embed.clock_offset` setting, which can be
+//! set per database ([PgEmbed::set_clock_offset]) or per session
+//! (`SET pg_embed.clock_offset = '3600'`). The sql keywords `CURRENT_TIMESTAMP`,
+//! `CURRENT_DATE` and `LOCALTIMESTAMP` and schema qualified calls are not affected.
+//!
+//! To shift the clock of the whole server, libfaketime can be preloaded with
+//! [PgSettings::env](crate::postgres::PgSettings::env) (`LD_PRELOAD`, `FAKETIME`).
+//!
+use crate::pg_types::PgResult;
+use crate::postgres::{quote_identifier, PgEmbed};
+
+/// Schema of the clock function overrides
+const PG_EMBED_CLOCK_SCHEMA: &str = "pg_embed_clock";
+/// Setting holding the clock offset in seconds
+const PG_EMBED_CLOCK_OFFSET: &str = "pg_embed.clock_offset";
+
+impl PgEmbed {
+    ///
+    /// Install the fake clock functions into a database
+    ///
+    /// The `search_path` change applies to new sessions of the database.
+    ///
+    /// Returns `Ok(())` on success, otherwise returns an error.
+    ///
+    pub async fn install_fake_clock(&self, db_name: &str) -> PgResult<()> {
+        let offset = format!(
+            "make_interval(secs => coalesce(nullif(current_setting('{}', true), ''), '0')::float8)",
+            PG_EMBED_CLOCK_OFFSET
+        );
+        let mut sql = format!("CREATE SCHEMA IF NOT EXISTS {};", PG_EMBED_CLOCK_SCHEMA);
+        for (function, volatility) in &[
+            ("now", "STABLE"),
+            ("transaction_timestamp", "STABLE"),
+            ("statement_timestamp", "STABLE"),
+            ("clock_timestamp", "VOLATILE"),
+        ] {
+            sql.push_str(&format!(
+                "CREATE OR REPLACE FUNCTION {schema}.{function}() RETURNS timestamptz \
+                 LANGUAGE sql {volatility} AS $$ SELECT pg_catalog.{function}() + {offset} $$;",
+                schema = PG_EMBED_CLOCK_SCHEMA,
+                function = function,
+                volatility = volatility,
+                offset = offset
+            ));
+        }
+        sql.push_str(&format!(
+            "ALTER DATABASE {} SET search_path = {}, pg_catalog, \"$user\", public;",
+            quote_identifier(db_name),
+            PG_EMBED_CLOCK_SCHEMA
+        ));
+        self.psql_exec(db_name, &sql).await?;
+        Ok(())
+    }
+
+    ///
+    /// Set the fake clock offset of a database in seconds
+    ///
+    /// Applies to new sessions of the database, requires [PgEmbed::install_fake_clock].
+    /// Negative offsets move the clock back.
+    ///
+    /// Returns `Ok(())` on success, otherwise returns an error.
+    ///
+    pub async fn set_clock_offset(&self, db_name: &str, offset_secs: i64) -> PgResult<()> {
+        let sql = format!(
+            "ALTER DATABASE {} SET {} = '{}'",
+            quote_identifier(db_name),
+            PG_EMBED_CLOCK_OFFSET,
+            offset_secs
+        );
+        self.psql_exec(db_name, &sql).await?;
+        Ok(())
+    }
+}
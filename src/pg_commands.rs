@@ -3,11 +3,14 @@
 //!
 //! Command executors for initdb, pg_ctl start, pg_ctl stop
 //!
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
 
 use crate::command_executor::{AsyncCommand, AsyncCommandExecutor};
+use crate::pg_config::PgLocale;
 use crate::pg_enums::{PgAuthMethod, PgProcessType, PgServerStatus};
-use crate::pg_errors::PgEmbedError;
+use crate::pg_errors::{PgEmbedError, PgEmbedErrorType};
 use crate::pg_types::PgResult;
 
 ///
@@ -17,36 +20,95 @@ pub struct PgCommand {}
 
 impl PgCommand {
     ///
-    /// Create initdb command
+    /// Environment of postgresql child processes
     ///
-    pub fn init_db_executor(
-        init_db_exe: &PathBuf,
-        database_dir: &PathBuf,
-        pw_file_path: &PathBuf,
+    /// The inherited environment without `PG*` variables (unless `inherit_pg_env` is set),
+    /// extended by the user provided variables.
+    ///
+    pub fn child_env(
+        env: &HashMap<String, String>,
+        inherit_pg_env: bool,
+    ) -> HashMap<OsString, OsString> {
+        let mut vars: HashMap<OsString, OsString> = std::env::vars_os()
+            .filter(|(key, _)| inherit_pg_env || !key.to_string_lossy().starts_with("PG"))
+            .collect();
+        vars.extend(
+            env.iter()
+                .map(|(key, value)| (OsString::from(key), OsString::from(value))),
+        );
+        vars
+    }
+
+    ///
+    /// Check that an executable exists before it is spawned
+    ///
+    /// Returns `Ok(())` if the executable exists, otherwise returns a
+    /// [PgEmbedErrorType::BinaryMissing] error.
+    ///
+    pub fn check_executable(executable: &Path) -> PgResult<()> {
+        if executable.is_file() {
+            Ok(())
+        } else {
+            Err(PgEmbedError {
+                error_type: PgEmbedErrorType::BinaryMissing(executable.to_path_buf()),
+                source: None,
+                message: None,
+            })
+        }
+    }
+
+    ///
+    /// initdb arguments determining the initialized cluster
+    ///
+    /// Excludes the database directory and password file arguments.
+    ///
+    pub fn init_db_settings_args(
         user: &str,
         auth_method: &PgAuthMethod,
-    ) -> PgResult<AsyncCommandExecutor<PgServerStatus, PgEmbedError, PgProcessType>> {
-        let init_db_executable = init_db_exe.as_os_str();
-        let password_file_arg = format!("--pwfile={}", pw_file_path.to_str().unwrap());
+        locale: &PgLocale,
+    ) -> Vec<String> {
         let auth_host = match auth_method {
             PgAuthMethod::Plain => "password",
             PgAuthMethod::MD5 => "md5",
             PgAuthMethod::ScramSha256 => "scram-sha-256",
         };
-        let args = [
-            "-A",
-            auth_host,
-            "-U",
-            user,
-            "-D",
-            database_dir.to_str().unwrap(),
-            &password_file_arg,
+        let mut args = vec![
+            "-A".to_string(),
+            auth_host.to_string(),
+            "-U".to_string(),
+            user.to_string(),
         ];
+        args.extend(locale.init_db_args());
+        args
+    }
+
+    ///
+    /// Create initdb command
+    ///
+    pub fn init_db_executor(
+        init_db_exe: &Path,
+        database_dir: &Path,
+        pw_file_path: &Path,
+        user: &str,
+        auth_method: &PgAuthMethod,
+        locale: &PgLocale,
+        envs: &HashMap<OsString, OsString>,
+    ) -> PgResult<AsyncCommandExecutor<PgServerStatus, PgEmbedError, PgProcessType>> {
+        Self::check_executable(init_db_exe)?;
+        let init_db_executable = init_db_exe.as_os_str();
+        let password_file_arg = format!("--pwfile={}", pw_file_path.to_str().unwrap());
+        let mut args = Self::init_db_settings_args(user, auth_method, locale);
+        args.extend(vec![
+            "-D".to_string(),
+            database_dir.to_str().unwrap().to_string(),
+            password_file_arg,
+        ]);
 
         let command_executor =
             AsyncCommandExecutor::<PgServerStatus, PgEmbedError, PgProcessType>::new(
                 init_db_executable,
                 args,
+                envs,
                 PgProcessType::InitDb,
             )?;
 
@@ -60,7 +122,9 @@ impl PgCommand {
         pg_ctl_exe: &PathBuf,
         database_dir: &PathBuf,
         port: &u16,
+        envs: &HashMap<OsString, OsString>,
     ) -> PgResult<AsyncCommandExecutor<PgServerStatus, PgEmbedError, PgProcessType>> {
+        Self::check_executable(pg_ctl_exe)?;
         let pg_ctl_executable = pg_ctl_exe.as_os_str();
         let port_arg = format!("-F -p {}", port.to_string());
         let args = [
@@ -75,6 +139,7 @@ impl PgCommand {
             AsyncCommandExecutor::<PgServerStatus, PgEmbedError, PgProcessType>::new(
                 pg_ctl_executable,
                 args,
+                envs,
                 PgProcessType::StartDb,
             )?;
 
@@ -87,13 +152,16 @@ impl PgCommand {
     pub fn stop_db_executor(
         pg_ctl_exe: &PathBuf,
         database_dir: &PathBuf,
+        envs: &HashMap<OsString, OsString>,
     ) -> PgResult<AsyncCommandExecutor<PgServerStatus, PgEmbedError, PgProcessType>> {
+        Self::check_executable(pg_ctl_exe)?;
         let pg_ctl_executable = pg_ctl_exe.as_os_str();
         let args = ["stop", "-w", "-D", database_dir.to_str().unwrap()];
         let command_executor =
             AsyncCommandExecutor::<PgServerStatus, PgEmbedError, PgProcessType>::new(
                 pg_ctl_executable,
                 args,
+                envs,
                 PgProcessType::StopDb,
             )?;
 
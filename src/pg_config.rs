@@ -0,0 +1,391 @@
+//!
+//! Postgresql server configuration
+//!
+//! Configuration parameters written to the database cluster before the server is started.
+//!
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::pg_enums::{PgArchiveMode, PgLocaleProvider, PgWalLevel};
+
+/// Name of the configuration file included by postgresql.conf
+pub const PG_EMBED_CONFIG_FILE_NAME: &str = "pg_embed.conf";
+
+///
+/// Postgresql server configuration
+///
+/// The parameters are written to `pg_embed.conf` in the database directory,
+/// which is included at the end of `postgresql.conf` and therefore overrides its values.
+///
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PgConfig {
+    /// libraries preloaded at server start (`shared_preload_libraries`),
+    /// e.g. `pg_stat_statements`
+    pub shared_preload_libraries: Vec<String>,
+    /// maximum number of background processes (`max_worker_processes`)
+    pub max_worker_processes: Option<u32>,
+    /// maximum number of parallel workers (`max_parallel_workers`)
+    pub max_parallel_workers: Option<u32>,
+    /// maximum number of parallel workers per gather node (`max_parallel_workers_per_gather`)
+    pub max_parallel_workers_per_gather: Option<u32>,
+    /// time zone for displaying and interpreting time stamps (`timezone`), e.g. `UTC`
+    pub timezone: Option<String>,
+    /// connection and memory sizing
+    pub resources: Option<PgResourceProfile>,
+    /// write ahead log, archiving and recovery
+    pub wal: PgWalConfig,
+    /// server log files and rotation
+    pub log: PgLogConfig,
+    /// additional configuration parameters (e.g. `pg_stat_statements.track = "all"`)
+    pub settings: BTreeMap<String, String>,
+}
+
+impl PgConfig {
+    ///
+    /// Configuration parameters as key value pairs
+    ///
+    /// Values of [PgConfig::settings] take precedence over the typed parameters.
+    ///
+    pub fn parameters(&self) -> BTreeMap<String, String> {
+        let mut parameters = BTreeMap::new();
+        if !self.shared_preload_libraries.is_empty() {
+            parameters.insert(
+                "shared_preload_libraries".to_string(),
+                self.shared_preload_libraries.join(","),
+            );
+        }
+        if let Some(value) = self.max_worker_processes {
+            parameters.insert("max_worker_processes".to_string(), value.to_string());
+        }
+        if let Some(value) = self.max_parallel_workers {
+            parameters.insert("max_parallel_workers".to_string(), value.to_string());
+        }
+        if let Some(value) = self.max_parallel_workers_per_gather {
+            parameters.insert(
+                "max_parallel_workers_per_gather".to_string(),
+                value.to_string(),
+            );
+        }
+        if let Some(value) = &self.timezone {
+            parameters.insert("timezone".to_string(), value.clone());
+        }
+        if let Some(resources) = &self.resources {
+            parameters.extend(resources.parameters());
+        }
+        parameters.extend(self.wal.parameters());
+        parameters.extend(self.log.parameters());
+        parameters.extend(self.settings.clone());
+        parameters
+    }
+
+    ///
+    /// Add a library to `shared_preload_libraries` unless already preloaded
+    ///
+    /// A `shared_preload_libraries` entry of [PgConfig::settings] is extended as well,
+    /// as it takes precedence over [PgConfig::shared_preload_libraries].
+    ///
+    pub fn preload_library(&mut self, library: &str) {
+        if !self.shared_preload_libraries.iter().any(|l| l == library) {
+            self.shared_preload_libraries.push(library.to_string());
+        }
+        if let Some(libraries) = self.settings.get_mut("shared_preload_libraries") {
+            if !libraries.split(',').any(|l| l.trim() == library) {
+                if !libraries.trim().is_empty() {
+                    libraries.push(',');
+                }
+                libraries.push_str(library);
+            }
+        }
+    }
+
+    ///
+    /// Configuration file content
+    ///
+    pub fn to_config_file(&self) -> String {
+        let mut content = String::from("# generated by pg-embed, do not edit\n");
+        for (key, value) in self.parameters() {
+            content.push_str(&format!("{} = '{}'\n", key, value.replace('\'', "''")));
+        }
+        content
+    }
+}
+
+///
+/// Database cluster locale settings
+///
+/// Passed to initdb when the database cluster is created.
+/// Unset values are determined by initdb from the environment.
+///
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PgLocale {
+    /// default locale (`--locale`)
+    pub locale: Option<String>,
+    /// locale provider (`--locale-provider`), postgresql versions >= 15
+    pub locale_provider: Option<PgLocaleProvider>,
+    /// ICU locale used with the ICU locale provider (`--icu-locale`), e.g. `en-US`
+    pub icu_locale: Option<String>,
+    /// collation order (`--lc-collate`)
+    pub lc_collate: Option<String>,
+    /// character classification (`--lc-ctype`)
+    pub lc_ctype: Option<String>,
+    /// message language (`--lc-messages`)
+    pub lc_messages: Option<String>,
+    /// monetary formatting (`--lc-monetary`)
+    pub lc_monetary: Option<String>,
+    /// number formatting (`--lc-numeric`)
+    pub lc_numeric: Option<String>,
+    /// date and time formatting (`--lc-time`)
+    pub lc_time: Option<String>,
+    /// database encoding (`--encoding`), e.g. `UTF8`
+    pub encoding: Option<String>,
+}
+
+impl PgLocale {
+    ///
+    /// initdb arguments
+    ///
+    pub fn init_db_args(&self) -> Vec<String> {
+        let options = [
+            ("--locale", self.locale.clone()),
+            (
+                "--locale-provider",
+                self.locale_provider.map(|p| p.to_string()),
+            ),
+            ("--icu-locale", self.icu_locale.clone()),
+            ("--lc-collate", self.lc_collate.clone()),
+            ("--lc-ctype", self.lc_ctype.clone()),
+            ("--lc-messages", self.lc_messages.clone()),
+            ("--lc-monetary", self.lc_monetary.clone()),
+            ("--lc-numeric", self.lc_numeric.clone()),
+            ("--lc-time", self.lc_time.clone()),
+            ("--encoding", self.encoding.clone()),
+        ];
+        options
+            .iter()
+            .filter_map(|(option, value)| {
+                value.as_ref().map(|value| format!("{}={}", option, value))
+            })
+            .collect()
+    }
+}
+
+///
+/// Connection and memory sizing
+///
+/// Memory values use the postgresql unit syntax (e.g. `128MB`).
+/// Unset values keep the postgresql defaults.
+///
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PgResourceProfile {
+    /// maximum number of concurrent connections (`max_connections`)
+    pub max_connections: Option<u32>,
+    /// shared memory buffers (`shared_buffers`)
+    pub shared_buffers: Option<String>,
+    /// memory per query operation (`work_mem`)
+    pub work_mem: Option<String>,
+    /// memory for maintenance operations (`maintenance_work_mem`)
+    pub maintenance_work_mem: Option<String>,
+    /// planner assumption of the available disk cache (`effective_cache_size`)
+    pub effective_cache_size: Option<String>,
+}
+
+impl PgResourceProfile {
+    ///
+    /// Size the settings based on the detected system memory
+    ///
+    /// Falls back to the postgresql defaults if the system memory can't be detected.
+    ///
+    pub fn auto() -> Self {
+        match system_memory_mb() {
+            Some(memory) => Self::for_memory(memory),
+            None => Self::default(),
+        }
+    }
+
+    ///
+    /// Size the settings for the given system memory in megabytes
+    ///
+    pub fn for_memory(memory_mb: u64) -> Self {
+        let max_connections = 100;
+        let shared_buffers = (memory_mb / 8).clamp(32, 1024);
+        let work_mem = (memory_mb / (max_connections * 16)).clamp(1, 64);
+        let maintenance_work_mem = (memory_mb / 32).clamp(16, 512);
+        let effective_cache_size = (memory_mb / 4).max(128);
+        PgResourceProfile {
+            max_connections: Some(max_connections as u32),
+            shared_buffers: Some(format!("{}MB", shared_buffers)),
+            work_mem: Some(format!("{}MB", work_mem)),
+            maintenance_work_mem: Some(format!("{}MB", maintenance_work_mem)),
+            effective_cache_size: Some(format!("{}MB", effective_cache_size)),
+        }
+    }
+
+    ///
+    /// Configuration parameters as key value pairs
+    ///
+    pub fn parameters(&self) -> BTreeMap<String, String> {
+        let options = [
+            (
+                "max_connections",
+                self.max_connections.map(|v| v.to_string()),
+            ),
+            ("shared_buffers", self.shared_buffers.clone()),
+            ("work_mem", self.work_mem.clone()),
+            ("maintenance_work_mem", self.maintenance_work_mem.clone()),
+            ("effective_cache_size", self.effective_cache_size.clone()),
+        ];
+        options
+            .iter()
+            .filter_map(|(key, value)| value.as_ref().map(|value| (key.to_string(), value.clone())))
+            .collect()
+    }
+}
+
+///
+/// Write ahead log configuration
+///
+/// Used to build point-in-time-recovery and replication scenarios.
+/// `archive_command` and `restore_command` may use the `%p` (file path) and `%f` (file name)
+/// placeholders.
+///
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PgWalConfig {
+    /// amount of information written to the write ahead log (`wal_level`)
+    pub wal_level: Option<PgWalLevel>,
+    /// archiving of completed segments (`archive_mode`)
+    pub archive_mode: Option<PgArchiveMode>,
+    /// shell command to archive a segment (`archive_command`)
+    pub archive_command: Option<String>,
+    /// forced segment switch interval in seconds (`archive_timeout`)
+    pub archive_timeout: Option<u32>,
+    /// shell command to retrieve an archived segment (`restore_command`), postgresql versions >= 12
+    pub restore_command: Option<String>,
+    /// maximum number of concurrent replication connections (`max_wal_senders`)
+    pub max_wal_senders: Option<u32>,
+}
+
+impl PgWalConfig {
+    ///
+    /// Configuration parameters as key value pairs
+    ///
+    pub fn parameters(&self) -> BTreeMap<String, String> {
+        let options = [
+            ("wal_level", self.wal_level.map(|v| v.to_string())),
+            ("archive_mode", self.archive_mode.map(|v| v.to_string())),
+            ("archive_command", self.archive_command.clone()),
+            (
+                "archive_timeout",
+                self.archive_timeout.map(|v| v.to_string()),
+            ),
+            ("restore_command", self.restore_command.clone()),
+            (
+                "max_wal_senders",
+                self.max_wal_senders.map(|v| v.to_string()),
+            ),
+        ];
+        options
+            .iter()
+            .filter_map(|(key, value)| value.as_ref().map(|value| (key.to_string(), value.clone())))
+            .collect()
+    }
+}
+
+///
+/// Server log file configuration
+///
+/// Without the logging collector the server log is written to the pg_ctl output, which is
+/// forwarded to the `log` crate. With the collector the log files can be read with
+/// [PgEmbed::log_path](crate::postgres::PgEmbed::log_path) and
+/// [PgEmbed::read_log_tail](crate::postgres::PgEmbed::read_log_tail).
+///
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PgLogConfig {
+    /// write the server log to files (`logging_collector`), required by the other parameters
+    pub collector: bool,
+    /// log file directory (`log_directory`), absolute or relative to the database directory
+    pub directory: Option<PathBuf>,
+    /// log file name pattern (`log_filename`), may contain strftime escapes
+    pub filename: Option<String>,
+    /// log file size in kilobytes triggering a rotation (`log_rotation_size`)
+    pub rotation_size_kb: Option<u32>,
+    /// log file age in minutes triggering a rotation (`log_rotation_age`)
+    pub rotation_age_minutes: Option<u32>,
+}
+
+impl PgLogConfig {
+    ///
+    /// Configuration parameters as key value pairs
+    ///
+    pub fn parameters(&self) -> BTreeMap<String, String> {
+        if !self.collector {
+            return BTreeMap::new();
+        }
+        let options = [
+            ("logging_collector", Some("on".to_string())),
+            (
+                "log_directory",
+                self.directory
+                    .as_ref()
+                    .map(|v| v.to_string_lossy().to_string()),
+            ),
+            ("log_filename", self.filename.clone()),
+            (
+                "log_rotation_size",
+                self.rotation_size_kb.map(|v| v.to_string()),
+            ),
+            (
+                "log_rotation_age",
+                self.rotation_age_minutes.map(|v| v.to_string()),
+            ),
+        ];
+        options
+            .iter()
+            .filter_map(|(key, value)| value.as_ref().map(|value| (key.to_string(), value.clone())))
+            .collect()
+    }
+}
+
+///
+/// Total system memory in megabytes
+///
+#[cfg(target_os = "linux")]
+fn system_memory_mb() -> Option<u64> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    meminfo
+        .lines()
+        .find(|line| line.starts_with("MemTotal:"))
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|kb| kb.parse::<u64>().ok())
+        .map(|kb| kb / 1024)
+}
+
+///
+/// Total system memory in megabytes
+///
+#[cfg(target_os = "macos")]
+fn system_memory_mb() -> Option<u64> {
+    let output = std::process::Command::new("sysctl")
+        .args(["-n", "hw.memsize"])
+        .output()
+        .ok()?;
+    String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .map(|bytes| bytes / 1024 / 1024)
+}
+
+///
+/// Total system memory in megabytes
+///
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn system_memory_mb() -> Option<u64> {
+    None
+}
@@ -3,8 +3,17 @@
 //!
 
 use std::error::Error;
+use std::fmt;
+use std::io::Read;
+use std::path::Path;
+use std::process::ExitStatus;
+use std::time::Duration;
 
-use crate::command_executor::ProcessStatus;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::command_executor::{LogType, ProcessStatus};
 use crate::pg_errors::{PgEmbedError, PgEmbedErrorType};
 
 ///
@@ -13,15 +22,341 @@ use crate::pg_errors::{PgEmbedError, PgEmbedErrorType};
 /// Choose between plain password, md5 or scram_sha_256 authentication.
 /// Scram_sha_256 authentication is only available on postgresql versions >= 11
 ///
+#[derive(Serialize, Deserialize)]
 pub enum PgAuthMethod {
     /// plain-text
+    #[serde(rename = "plain")]
     Plain,
     /// md5
+    #[serde(rename = "md5")]
     MD5,
     /// scram_sha_256
+    #[serde(rename = "scram-sha-256")]
     ScramSha256,
 }
 
+///
+/// Postgresql password source
+///
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PgPassword {
+    /// random alphanumeric password of the given length
+    Random(usize),
+    /// password read from the given environment variable
+    FromEnv(String),
+    /// password value
+    Value(String),
+}
+
+impl PgPassword {
+    ///
+    /// Resolve the password
+    ///
+    /// Returns the password on success, otherwise returns an error.
+    ///
+    pub fn resolve(&self) -> Result<String, PgEmbedError> {
+        match self {
+            PgPassword::Random(len) => Ok(rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(*len)
+                .map(char::from)
+                .collect()),
+            PgPassword::FromEnv(var) => std::env::var(var).map_err(|e| PgEmbedError {
+                error_type: PgEmbedErrorType::InvalidConfig,
+                source: Some(Box::new(e)),
+                message: Some(format!("password environment variable {} not set", var)),
+            }),
+            PgPassword::Value(password) => Ok(password.clone()),
+        }
+    }
+}
+
+impl Default for PgPassword {
+    fn default() -> Self {
+        PgPassword::Value("password".to_string())
+    }
+}
+
+impl From<&str> for PgPassword {
+    fn from(password: &str) -> Self {
+        PgPassword::Value(password.to_string())
+    }
+}
+
+impl From<String> for PgPassword {
+    fn from(password: String) -> Self {
+        PgPassword::Value(password)
+    }
+}
+
+///
+/// Migration policy of the configured databases
+///
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum PgMigrationPolicy {
+    /// databases are created and migrated by the caller
+    #[default]
+    Manual,
+    /// create the missing databases and apply the migrations to them
+    /// once per [PgEmbed::setup](crate::postgres::PgEmbed::setup),
+    /// on the first server start that follows it
+    ApplyOnSetup {
+        /// database names
+        databases: Vec<String>,
+    },
+}
+
+///
+/// Transaction mode of the migrations
+///
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum PgMigrationTransaction {
+    /// each migration file runs in its own transaction
+    #[default]
+    PerFile,
+    /// all pending migrations run in a single transaction
+    Single,
+    /// migrations run without transaction (e.g. `CREATE INDEX CONCURRENTLY`)
+    None,
+}
+
+///
+/// Kind of a migration drift reported by
+/// [PgEmbed::verify_migrations](crate::postgres::PgEmbed::verify_migrations)
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PgMigrationDriftKind {
+    /// the migration file was edited after the migration was applied
+    Modified,
+    /// the applied migration has no migration file
+    Missing,
+}
+
+///
+/// Postgresql locale provider
+///
+/// The ICU locale provider is only available on postgresql versions >= 15
+///
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PgLocaleProvider {
+    /// operating system locales
+    Libc,
+    /// ICU library locales
+    Icu,
+}
+
+impl fmt::Display for PgLocaleProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PgLocaleProvider::Libc => f.write_str("libc"),
+            PgLocaleProvider::Icu => f.write_str("icu"),
+        }
+    }
+}
+
+///
+/// Postgresql write ahead log level
+///
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PgWalLevel {
+    /// only information needed for crash recovery (requires `max_wal_senders = 0`)
+    Minimal,
+    /// information needed for archiving and replication
+    Replica,
+    /// information needed for logical decoding
+    Logical,
+}
+
+impl fmt::Display for PgWalLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PgWalLevel::Minimal => f.write_str("minimal"),
+            PgWalLevel::Replica => f.write_str("replica"),
+            PgWalLevel::Logical => f.write_str("logical"),
+        }
+    }
+}
+
+///
+/// Postgresql write ahead log archive mode
+///
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PgArchiveMode {
+    /// no archiving
+    Off,
+    /// archiving on the primary server
+    On,
+    /// archiving on the primary and standby servers
+    Always,
+}
+
+impl fmt::Display for PgArchiveMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PgArchiveMode::Off => f.write_str("off"),
+            PgArchiveMode::On => f.write_str("on"),
+            PgArchiveMode::Always => f.write_str("always"),
+        }
+    }
+}
+
+///
+/// pg_dump output format
+///
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PgDumpFormat {
+    /// plain sql script, restored with psql
+    Plain,
+    /// compressed custom archive, restored with pg_restore
+    Custom,
+    /// directory with one file per table, restored with pg_restore
+    Directory,
+    /// tar archive, restored with pg_restore
+    Tar,
+}
+
+impl PgDumpFormat {
+    ///
+    /// Detect the format of a dump
+    ///
+    /// Custom archives start with `PGDMP`, tar archives have the `ustar` magic at offset 257,
+    /// other files are plain sql scripts.
+    ///
+    /// Returns the format on success, otherwise returns an error.
+    ///
+    pub fn detect(path: &Path) -> Result<Self, PgEmbedError> {
+        if path.is_dir() {
+            return Ok(PgDumpFormat::Directory);
+        }
+        let to_read_error = |e: std::io::Error| PgEmbedError {
+            error_type: PgEmbedErrorType::ReadFileError,
+            source: Some(Box::new(e)),
+            message: Some(format!("could not read dump {}", path.display())),
+        };
+        let mut header = Vec::with_capacity(512);
+        std::fs::File::open(path)
+            .and_then(|file| file.take(512).read_to_end(&mut header))
+            .map_err(to_read_error)?;
+        if header.starts_with(b"PGDMP") {
+            Ok(PgDumpFormat::Custom)
+        } else if header.len() >= 262 && &header[257..262] == b"ustar" {
+            Ok(PgDumpFormat::Tar)
+        } else {
+            Ok(PgDumpFormat::Plain)
+        }
+    }
+
+    ///
+    /// File extension of the dump, empty for the directory format
+    ///
+    pub fn file_extension(&self) -> &str {
+        match self {
+            PgDumpFormat::Plain => "sql",
+            PgDumpFormat::Custom => "dump",
+            PgDumpFormat::Directory => "",
+            PgDumpFormat::Tar => "tar",
+        }
+    }
+}
+
+impl fmt::Display for PgDumpFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PgDumpFormat::Plain => f.write_str("plain"),
+            PgDumpFormat::Custom => f.write_str("custom"),
+            PgDumpFormat::Directory => f.write_str("directory"),
+            PgDumpFormat::Tar => f.write_str("tar"),
+        }
+    }
+}
+
+///
+/// Severity of a server log entry
+///
+/// Ordered by importance for filtering, `LOG` entries rank below `INFO`
+/// (unlike the `log_min_messages` order).
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PgLogLevel {
+    /// `DEBUG1` to `DEBUG5`
+    Debug,
+    /// `LOG`
+    Log,
+    /// `INFO`
+    Info,
+    /// `NOTICE`
+    Notice,
+    /// `WARNING`
+    Warning,
+    /// `ERROR`
+    Error,
+    /// `FATAL`
+    Fatal,
+    /// `PANIC`
+    Panic,
+}
+
+impl PgLogLevel {
+    ///
+    /// Parse the severity of a server log entry
+    ///
+    /// Returns the level on success, `None` for unknown severities.
+    ///
+    pub fn parse(severity: &str) -> Option<Self> {
+        match severity {
+            "DEBUG1" | "DEBUG2" | "DEBUG3" | "DEBUG4" | "DEBUG5" => Some(PgLogLevel::Debug),
+            "LOG" => Some(PgLogLevel::Log),
+            "INFO" => Some(PgLogLevel::Info),
+            "NOTICE" => Some(PgLogLevel::Notice),
+            "WARNING" => Some(PgLogLevel::Warning),
+            "ERROR" => Some(PgLogLevel::Error),
+            "FATAL" => Some(PgLogLevel::Fatal),
+            "PANIC" => Some(PgLogLevel::Panic),
+            _ => None,
+        }
+    }
+}
+
+///
+/// Lifecycle event reported to the callbacks registered with
+/// [PgEmbed::on_event](crate::postgres::PgEmbed::on_event)
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum PgEvent {
+    /// download of the postgresql binaries started
+    DownloadStarted,
+    /// download of the postgresql binaries finished
+    DownloadFinished {
+        /// download duration
+        duration: Duration,
+    },
+    /// database cluster initialization started
+    InitDbStarted,
+    /// database cluster initialization finished
+    InitDbFinished {
+        /// initialization duration
+        duration: Duration,
+    },
+    /// server started
+    ServerStarted {
+        /// server port
+        port: u16,
+    },
+    /// server stopped
+    ServerStopped,
+    /// server terminated unexpectedly
+    Crashed,
+    /// database cluster files removed on drop
+    CleanupDone,
+}
+
 ///
 /// Postgresql server status
 ///
@@ -107,6 +442,33 @@ impl ProcessStatus<PgServerStatus, PgEmbedError> for PgProcessType {
             message,
         }
     }
+
+    fn failure_error(
+        &self,
+        command_line: &str,
+        exit_status: ExitStatus,
+        output: &str,
+    ) -> PgEmbedError {
+        let mut error = self.error_type();
+        let mut message = format!("{} failed with {}", command_line, exit_status);
+        if !output.trim().is_empty() {
+            message.push_str(":\n");
+            message.push_str(output.trim());
+        }
+        error.message = Some(message);
+        error
+    }
+
+    fn log_target(&self, stream: &LogType) -> &'static str {
+        match (self, stream) {
+            (PgProcessType::InitDb, _) => "pg_embed::initdb",
+            // the started server inherits the stderr of pg_ctl
+            (PgProcessType::StartDb, LogType::Error) => "pg_embed::server",
+            (PgProcessType::StartDb, LogType::Info) | (PgProcessType::StopDb, _) => {
+                "pg_embed::pg_ctl"
+            }
+        }
+    }
 }
 
 impl ToString for PgProcessType {
@@ -120,12 +482,16 @@ impl ToString for PgProcessType {
 }
 
 /// The operation systems enum
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum OperationSystem {
     Darwin,
     Windows,
     Linux,
     AlpineLinux,
+    /// Operation system not covered by the default repository (e.g. `freebsd` or `illumos`),
+    /// requires a custom provider or [artifact template](crate::pg_fetch::PgFetchSettings::artifact_template)
+    Custom(String),
 }
 
 impl ToString for OperationSystem {
@@ -135,31 +501,43 @@ impl ToString for OperationSystem {
             OperationSystem::Windows => "windows".to_string(),
             OperationSystem::Linux => "linux".to_string(),
             OperationSystem::AlpineLinux => "linux".to_string(),
+            OperationSystem::Custom(name) => name.clone(),
         }
     }
 }
 
-impl Default for OperationSystem {
-    fn default() -> Self {
-        #[cfg(not(any(target_os = "linux", target_os = "windows")))]
-        {
-            OperationSystem::Darwin
-        }
-
-        #[cfg(target_os = "linux")]
-        {
-            OperationSystem::Linux
+impl OperationSystem {
+    ///
+    /// Detect the operation system of the host
+    ///
+    /// Linux hosts with a musl libc (e.g. Alpine containers) detect [OperationSystem::AlpineLinux],
+    /// as the glibc binaries fail there with dynamic linker errors.
+    ///
+    /// Operation systems not covered by the default repository detect [OperationSystem::Custom].
+    ///
+    pub fn detect() -> Self {
+        match std::env::consts::OS {
+            "macos" => OperationSystem::Darwin,
+            "windows" => OperationSystem::Windows,
+            "linux" if is_musl() => OperationSystem::AlpineLinux,
+            "linux" => OperationSystem::Linux,
+            os => OperationSystem::Custom(os.to_string()),
         }
+    }
+}
 
-        #[cfg(target_os = "windows")]
-        {
-            OperationSystem::Windows
-        }
+impl Default for OperationSystem {
+    ///
+    /// The detected operation system
+    ///
+    fn default() -> Self {
+        OperationSystem::detect()
     }
 }
 
 /// The cpu architectures enum
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Architecture {
     Amd64,
     I386,
@@ -182,38 +560,82 @@ impl ToString for Architecture {
     }
 }
 
-impl Default for Architecture {
-    fn default() -> Self {
-        #[cfg(not(any(
-            target_arch = "x86",
-            target_arch = "arm",
-            target_arch = "aarch64",
-            target_arch = "powerpc64"
-        )))]
-        {
-            Architecture::Amd64
-        }
-
-        #[cfg(target_arch = "x86")]
-        {
-            Architecture::I386
+impl Architecture {
+    ///
+    /// Detect the cpu architecture of the host
+    ///
+    /// Processes translated by Rosetta 2 on Apple silicon detect arm64v8,
+    /// arm linux hosts reporting an ARMv6 cpu detect arm32v6.
+    ///
+    /// Returns `None` if the architecture isn't supported by the default repository.
+    ///
+    pub fn detect() -> Option<Self> {
+        match std::env::consts::ARCH {
+            "x86_64" if is_rosetta_translated() => Some(Architecture::Arm64v8),
+            "x86_64" => Some(Architecture::Amd64),
+            "x86" => Some(Architecture::I386),
+            "arm" if is_armv6_cpu() => Some(Architecture::Arm32v6),
+            "arm" => Some(Architecture::Arm32v7),
+            "aarch64" => Some(Architecture::Arm64v8),
+            "powerpc64" if cfg!(target_endian = "little") => Some(Architecture::Ppc64le),
+            _ => None,
         }
+    }
+}
 
-        #[cfg(target_arch = "arm")]
-        {
-            Architecture::Arm32v7
-        }
+impl Default for Architecture {
+    ///
+    /// The detected cpu architecture, falling back to amd64 for unsupported architectures
+    ///
+    fn default() -> Self {
+        Architecture::detect().unwrap_or(Architecture::Amd64)
+    }
+}
 
-        #[cfg(target_arch = "aarch64")]
-        {
-            Architecture::Arm64v8
-        }
+///
+/// Check if the host uses the musl libc
+///
+/// True for musl targets, alpine hosts and hosts providing the musl but no glibc dynamic linker.
+///
+fn is_musl() -> bool {
+    if cfg!(target_env = "musl") || std::path::Path::new("/etc/alpine-release").exists() {
+        return true;
+    }
+    let has_loader = |prefix: &str| {
+        ["/lib", "/lib64"].iter().any(|dir| {
+            std::fs::read_dir(dir)
+                .map(|entries| {
+                    entries
+                        .filter_map(Result::ok)
+                        .any(|entry| entry.file_name().to_string_lossy().starts_with(prefix))
+                })
+                .unwrap_or(false)
+        })
+    };
+    has_loader("ld-musl-") && !has_loader("ld-linux")
+}
 
-        #[cfg(target_arch = "powerpc64")]
-        {
-            Architecture::Ppc64le
-        }
+///
+/// Check if the process is translated by Rosetta 2 on Apple silicon
+///
+fn is_rosetta_translated() -> bool {
+    if !cfg!(target_os = "macos") {
+        return false;
     }
+    std::process::Command::new("sysctl")
+        .args(["-in", "sysctl.proc_translated"])
+        .output()
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim() == "1")
+        .unwrap_or(false)
+}
+
+///
+/// Check if the linux cpu info reports an ARMv6 cpu
+///
+fn is_armv6_cpu() -> bool {
+    std::fs::read_to_string("/proc/cpuinfo")
+        .map(|cpu_info| cpu_info.contains("ARMv6") || cpu_info.contains("(v6l)"))
+        .unwrap_or(false)
 }
 
 /// The postgresql binaries acquisition status
@@ -223,6 +645,8 @@ pub enum PgAcquisitionStatus {
     InProgress,
     /// Finished acquiring postgresql binaries
     Finished,
+    /// Acquiring postgresql binaries failed
+    Failed,
     /// No acquisition
     Undefined,
 }
@@ -1,7 +1,18 @@
 //!
 //! Errors
 //!
+//! All operations return the single [PgEmbedError] type, regardless of the selected runtime,
+//! migration or http client features. Errors of optional dependencies (sqlx, tokio-postgres,
+//! refinery, diesel, reqwest, surf) are kept as [PgEmbedError::source], so the error type and
+//! the [PgEmbedErrorType] variants are the same for every feature combination.
+//!
+//! [PgEmbedError] is `Send + Sync + 'static`, so it can be returned from spawned tasks and
+//! converted into `anyhow::Error` or `Box<dyn Error + Send + Sync>`. The cause is available
+//! through [Error::source](std::error::Error::source) and the failure is matched on
+//! [PgEmbedError::kind].
+//!
 use std::error::Error;
+use std::path::PathBuf;
 
 use std::fmt;
 use std::fmt::Formatter;
@@ -11,25 +22,44 @@ use thiserror::Error;
 /// PgEmbed errors
 #[derive(Error, Debug)]
 pub struct PgEmbedError {
+    /// kind of the failure
     pub error_type: PgEmbedErrorType,
-    pub source: Option<Box<dyn Error + Sync + Send>>,
+    /// underlying error, returned by [Error::source](std::error::Error::source)
+    #[source]
+    pub source: Option<Box<dyn Error + Sync + Send + 'static>>,
+    /// context of the failure
     pub message: Option<String>,
 }
 
+impl PgEmbedError {
+    ///
+    /// Kind of the failure
+    ///
+    pub fn kind(&self) -> &PgEmbedErrorType {
+        &self.error_type
+    }
+}
+
 impl fmt::Display for PgEmbedError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "error_type: {:?}\nsource: \n{:?}\nmessage: \n{:?}\n",
-            self.error_type, self.source, self.message
-        )
+        write!(f, "{}", self.error_type)?;
+        if let Some(message) = &self.message {
+            write!(f, ": {}", message)?;
+        }
+        if let Some(source) = &self.source {
+            write!(f, ": {}", source)?;
+        }
+        Ok(())
     }
 }
 
 ///
 /// Common pg_embed errors, independent from features used
 ///
-#[derive(Debug, PartialEq)]
+/// New variants may be added in minor releases, so matches need a wildcard arm.
+///
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
 pub enum PgEmbedErrorType {
     /// Invalid postgresql binaries download url
     InvalidPgUrl,
@@ -75,4 +105,78 @@ pub enum PgEmbedErrorType {
     SqlQueryError,
     /// migration error
     MigrationError,
+    /// Invalid configuration file
+    InvalidConfig,
+    /// Downloaded postgresql binaries don't match the expected checksum
+    ChecksumMismatch,
+    /// Archive entries escape the target directory or exceed the unpack limits
+    UnsafeArchive,
+    /// Signature of the postgresql binaries is missing or invalid
+    InvalidSignature,
+    /// Unpacked executables could not be made executable
+    ExecutableFixupFailure,
+    /// Cache directory lock could not be acquired
+    CacheLockFailure,
+    /// Requested extension is not available in the postgresql installation
+    ExtensionNotAvailable,
+    /// The port of the server is already bound by another process
+    PortAlreadyInUse(u16),
+    /// The database directory to be initialized contains files
+    DataDirNotEmpty(PathBuf),
+    /// A postgresql executable doesn't exist
+    BinaryMissing(PathBuf),
+    /// No postgresql binaries are published for the operation system
+    UnsupportedPlatform,
+    /// The password file of the database cluster could not be created
+    AuthSetupFailed,
+}
+
+impl fmt::Display for PgEmbedErrorType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            PgEmbedErrorType::PortAlreadyInUse(port) => {
+                return write!(f, "port {} is already in use", port)
+            }
+            PgEmbedErrorType::DataDirNotEmpty(dir) => {
+                return write!(f, "database directory {} is not empty", dir.display())
+            }
+            PgEmbedErrorType::BinaryMissing(path) => {
+                return write!(f, "postgresql executable {} is missing", path.display())
+            }
+            PgEmbedErrorType::InvalidPgUrl => "invalid postgresql binaries download url",
+            PgEmbedErrorType::InvalidPgPackage => "invalid postgresql binaries package",
+            PgEmbedErrorType::WriteFileError => "could not write file",
+            PgEmbedErrorType::ReadFileError => "could not read file",
+            PgEmbedErrorType::DirCreationError => "could not create directory",
+            PgEmbedErrorType::UnpackFailure => "failed to unpack postgresql binaries",
+            PgEmbedErrorType::PgStartFailure => "postgresql could not be started",
+            PgEmbedErrorType::PgStopFailure => "postgresql could not be stopped",
+            PgEmbedErrorType::PgInitFailure => "postgresql could not be initialized",
+            PgEmbedErrorType::PgCleanUpFailure => "clean up error",
+            PgEmbedErrorType::PgPurgeFailure => "purging error",
+            PgEmbedErrorType::PgBufferReadError => "buffer read error",
+            PgEmbedErrorType::PgLockError => "lock error",
+            PgEmbedErrorType::PgProcessError => "child process error",
+            PgEmbedErrorType::PgTimedOutError => "operation timed out",
+            PgEmbedErrorType::PgTaskJoinError => "task join error",
+            PgEmbedErrorType::PgError => "postgresql error",
+            PgEmbedErrorType::DownloadFailure => "postgresql binaries download failure",
+            PgEmbedErrorType::ConversionFailure => "response bytes conversion failure",
+            PgEmbedErrorType::SendFailure => "channel send error",
+            PgEmbedErrorType::SqlQueryError => "sql query error",
+            PgEmbedErrorType::MigrationError => "migration error",
+            PgEmbedErrorType::InvalidConfig => "invalid configuration",
+            PgEmbedErrorType::ChecksumMismatch => "postgresql binaries checksum mismatch",
+            PgEmbedErrorType::UnsafeArchive => "unsafe postgresql binaries archive",
+            PgEmbedErrorType::InvalidSignature => "invalid postgresql binaries signature",
+            PgEmbedErrorType::ExecutableFixupFailure => {
+                "could not make the postgresql binaries executable"
+            }
+            PgEmbedErrorType::CacheLockFailure => "could not lock the cache directory",
+            PgEmbedErrorType::ExtensionNotAvailable => "extension not available",
+            PgEmbedErrorType::UnsupportedPlatform => "unsupported platform",
+            PgEmbedErrorType::AuthSetupFailed => "could not set up the authentication",
+        };
+        f.write_str(description)
+    }
 }
@@ -0,0 +1,156 @@
+//!
+//! Fault injection
+//!
+//! Simulate database outages to test the reconnect and failover logic of applications.
+//! Pausing and killing the server is only supported on unix platforms.
+//!
+use futures::TryFutureExt;
+
+use crate::pg_enums::{PgEvent, PgServerStatus};
+use crate::pg_errors::{PgEmbedError, PgEmbedErrorType};
+use crate::pg_types::PgResult;
+use crate::postgres::PgEmbed;
+
+impl PgEmbed {
+    ///
+    /// Freeze the server (`SIGSTOP`)
+    ///
+    /// The postmaster and its backends stop responding, open connections hang
+    /// until [PgEmbed::resume] is called.
+    ///
+    /// Returns `Ok(())` on success, otherwise returns an error.
+    ///
+    pub async fn pause(&self) -> PgResult<()> {
+        let pids = self.server_pids(true).await?;
+        signal("STOP", &pids).await
+    }
+
+    ///
+    /// Resume a server frozen by [PgEmbed::pause] (`SIGCONT`)
+    ///
+    /// Returns `Ok(())` on success, otherwise returns an error.
+    ///
+    pub async fn resume(&self) -> PgResult<()> {
+        let pids = self.server_pids(true).await?;
+        signal("CONT", &pids).await
+    }
+
+    ///
+    /// Crash the server (`SIGKILL` of the postmaster)
+    ///
+    /// The backends exit after the postmaster died. [PgEmbed::start_db] restarts the server,
+    /// which then runs crash recovery.
+    ///
+    /// Returns `Ok(())` on success, otherwise returns an error.
+    ///
+    pub async fn kill9(&mut self) -> PgResult<()> {
+        let pids = self.server_pids(false).await?;
+        signal("KILL", &pids).await?;
+        {
+            let mut server_status = self.server_status.lock().await;
+            *server_status = PgServerStatus::Stopped;
+        }
+        self.emit_event(PgEvent::Crashed);
+        Ok(())
+    }
+
+    ///
+    /// Terminate all client connections
+    ///
+    /// Returns `Ok(())` on success, otherwise returns an error.
+    ///
+    pub async fn drop_connections(&self) -> PgResult<()> {
+        self.psql_exec(
+            "postgres",
+            "SELECT pg_terminate_backend(pid) FROM pg_stat_activity \
+             WHERE backend_type = 'client backend' AND pid <> pg_backend_pid()",
+        )
+        .await?;
+        Ok(())
+    }
+
+    ///
+    /// Process ids of the postmaster and optionally its child processes
+    ///
+    async fn server_pids(&self, with_children: bool) -> PgResult<Vec<String>> {
+        let pid_file = self.pg_access.database_dir.join("postmaster.pid");
+        let content = tokio::fs::read_to_string(&pid_file)
+            .map_err(|e| PgEmbedError {
+                error_type: PgEmbedErrorType::ReadFileError,
+                source: Some(Box::new(e)),
+                message: Some(String::from("the server is not running")),
+            })
+            .await?;
+        let postmaster = content
+            .lines()
+            .next()
+            .map(|pid| pid.trim().to_string())
+            .filter(|pid| !pid.is_empty())
+            .ok_or_else(|| PgEmbedError {
+                error_type: PgEmbedErrorType::ReadFileError,
+                source: None,
+                message: Some(format!("invalid pid file {}", pid_file.display())),
+            })?;
+        let mut pids = vec![postmaster.clone()];
+        if with_children {
+            let output = tokio::process::Command::new("pgrep")
+                .arg("-P")
+                .arg(&postmaster)
+                .output()
+                .map_err(|e| PgEmbedError {
+                    error_type: PgEmbedErrorType::PgProcessError,
+                    source: Some(Box::new(e)),
+                    message: Some(String::from("could not list the server processes")),
+                })
+                .await?;
+            pids.extend(
+                String::from_utf8_lossy(&output.stdout)
+                    .lines()
+                    .map(|pid| pid.trim().to_string())
+                    .filter(|pid| !pid.is_empty()),
+            );
+        }
+        Ok(pids)
+    }
+}
+
+///
+/// Send the signal `name` to the processes `pids`
+///
+async fn signal(name: &str, pids: &[String]) -> PgResult<()> {
+    #[cfg(unix)]
+    {
+        let output = tokio::process::Command::new("kill")
+            .arg(format!("-{}", name))
+            .args(pids)
+            .output()
+            .map_err(|e| PgEmbedError {
+                error_type: PgEmbedErrorType::PgProcessError,
+                source: Some(Box::new(e)),
+                message: None,
+            })
+            .await?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(PgEmbedError {
+                error_type: PgEmbedErrorType::PgProcessError,
+                source: None,
+                message: Some(format!(
+                    "could not send SIG{} to the server: {}",
+                    name,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                )),
+            })
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = pids;
+        Err(PgEmbedError {
+            error_type: PgEmbedErrorType::PgProcessError,
+            source: None,
+            message: Some(format!("SIG{} is only supported on unix platforms", name)),
+        })
+    }
+}
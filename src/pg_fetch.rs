@@ -4,55 +4,517 @@
 //! Download and unpack postgresql binaries
 //!
 
-use bytes::Bytes;
+use std::fmt;
+use std::io::SeekFrom;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
 use futures::TryFutureExt;
-use reqwest::Response;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
 
 use crate::pg_enums::{Architecture, OperationSystem};
 use crate::pg_errors::{PgEmbedError, PgEmbedErrorType};
+use crate::pg_http::{
+    self, PgHttpClient, PgHttpRequest, STATUS_NOT_FOUND, STATUS_PARTIAL_CONTENT,
+    STATUS_RANGE_NOT_SATISFIABLE,
+};
+use crate::pg_provider::PgBinaryProvider;
 use crate::pg_types::PgResult;
 
-/// Postgresql version struct (simple version wrapper)
-pub struct PostgresVersion(pub &'static str);
+/// Minimum size of a range downloaded in parallel
+const MIN_PARALLEL_CHUNK_SIZE: u64 = 1024 * 1024;
+
+///
+/// Postgresql version
+///
+/// Versions are ordered by major, minor and patch version.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PostgresVersion {
+    major: u32,
+    minor: u32,
+    patch: u32,
+}
+
+/// Latest postgres version 17
+pub const PG_V17: PostgresVersion = PostgresVersion::new(17, 2, 0);
+/// Latest postgres version 16
+pub const PG_V16: PostgresVersion = PostgresVersion::new(16, 6, 0);
 /// Latest postgres version 15
-pub const PG_V15: PostgresVersion = PostgresVersion("15.1.0");
+pub const PG_V15: PostgresVersion = PostgresVersion::new(15, 1, 0);
 /// Latest postgres version 14
-pub const PG_V14: PostgresVersion = PostgresVersion("14.6.0");
+pub const PG_V14: PostgresVersion = PostgresVersion::new(14, 6, 0);
 /// Latest postgres version 13
-pub const PG_V13: PostgresVersion = PostgresVersion("13.9.0");
+pub const PG_V13: PostgresVersion = PostgresVersion::new(13, 9, 0);
 /// Latest postgres version 12
-pub const PG_V12: PostgresVersion = PostgresVersion("12.13.0");
+pub const PG_V12: PostgresVersion = PostgresVersion::new(12, 13, 0);
 /// Latest pstgres version 11
-pub const PG_V11: PostgresVersion = PostgresVersion("11.18.0");
+pub const PG_V11: PostgresVersion = PostgresVersion::new(11, 18, 0);
 /// Latest postgres version 10
-pub const PG_V10: PostgresVersion = PostgresVersion("10.23.0");
+pub const PG_V10: PostgresVersion = PostgresVersion::new(10, 23, 0);
 /// Latest postgres version 9
-pub const PG_V9: PostgresVersion = PostgresVersion("9.6.24");
+pub const PG_V9: PostgresVersion = PostgresVersion::new(9, 6, 24);
+
+/// Postgres version constants
+pub const PG_VERSIONS: [PostgresVersion; 9] = [
+    PG_V17, PG_V16, PG_V15, PG_V14, PG_V13, PG_V12, PG_V11, PG_V10, PG_V9,
+];
+
+impl PostgresVersion {
+    ///
+    /// Create a new version
+    ///
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        PostgresVersion {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    ///
+    /// Parse a version string (e.g. `"15.4.0"` or `"15.4"`)
+    ///
+    /// Pre-release versions (e.g. `"16.0.0-beta1"`) are rejected.
+    ///
+    /// Returns the version on success, otherwise returns an error.
+    ///
+    pub fn parse(version: &str) -> PgResult<Self> {
+        let invalid = || PgEmbedError {
+            error_type: PgEmbedErrorType::InvalidConfig,
+            source: None,
+            message: Some(format!("invalid postgresql version {}", version)),
+        };
+        let parts = version
+            .trim()
+            .split('.')
+            .map(|part| part.parse::<u32>().map_err(|_| invalid()))
+            .collect::<PgResult<Vec<u32>>>()?;
+        match parts[..] {
+            [major, minor] => Ok(Self::new(major, minor, 0)),
+            [major, minor, patch] => Ok(Self::new(major, minor, patch)),
+            _ => Err(invalid()),
+        }
+    }
+
+    /// The major version
+    pub fn major(&self) -> u32 {
+        self.major
+    }
+
+    /// The minor version
+    pub fn minor(&self) -> u32 {
+        self.minor
+    }
+
+    /// The patch version
+    pub fn patch(&self) -> u32 {
+        self.patch
+    }
+
+    /// Whether the scram-sha-256 authentication method is available (postgresql >= 10)
+    pub fn supports_scram(&self) -> bool {
+        self.major >= 10
+    }
+
+    /// Whether the icu locale provider is available in initdb (postgresql >= 15)
+    pub fn supports_icu_locale_provider(&self) -> bool {
+        self.major >= 15
+    }
+
+    /// Whether the `stats_temp_directory` setting is available (postgresql < 15)
+    pub fn supports_stats_temp_directory(&self) -> bool {
+        self.major < 15
+    }
+
+    /// Whether `DROP DATABASE ... WITH (FORCE)` is available (postgresql >= 13)
+    pub fn supports_drop_database_force(&self) -> bool {
+        self.major >= 13
+    }
+}
+
+impl FromStr for PostgresVersion {
+    type Err = PgEmbedError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl fmt::Display for PostgresVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl Serialize for PostgresVersion {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+///
+/// Deserialize a version
+///
+/// Accepts a full version (`"13.9.0"`) or the major version (`"13"`) of a version constant.
+///
+impl<'de> Deserialize<'de> for PostgresVersion {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let version = String::deserialize(deserializer)?;
+        if let Ok(major) = version.trim().parse::<u32>() {
+            return PG_VERSIONS
+                .iter()
+                .find(|v| v.major == major)
+                .copied()
+                .ok_or_else(|| {
+                    D::Error::custom(format!("unsupported postgresql version {}", version))
+                });
+        }
+        PostgresVersion::parse(&version).map_err(D::Error::custom)
+    }
+}
+
+///
+/// Postgresql version requirement
+///
+/// Resolved to the newest matching version published in the repository,
+/// see [PgFetchSettings::resolve_version].
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum PgVersionReq {
+    /// the newest published version (`"latest"`)
+    Latest,
+    /// the newest version of a major release (e.g. `"13"` or `"13.*"`)
+    Major(u32),
+    /// the newest version of a minor release (e.g. `"13.9"` or `"13.9.*"`)
+    Minor(u32, u32),
+}
+
+impl PgVersionReq {
+    ///
+    /// Check if a published version matches the requirement
+    ///
+    pub fn matches(&self, version: &PostgresVersion) -> bool {
+        match self {
+            PgVersionReq::Latest => true,
+            PgVersionReq::Major(major) => version.major() == *major,
+            PgVersionReq::Minor(major, minor) => {
+                version.major() == *major && version.minor() == *minor
+            }
+        }
+    }
+}
+
+impl FromStr for PgVersionReq {
+    type Err = PgEmbedError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || PgEmbedError {
+            error_type: PgEmbedErrorType::InvalidConfig,
+            source: None,
+            message: Some(format!("invalid postgresql version requirement {}", s)),
+        };
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("latest") {
+            return Ok(PgVersionReq::Latest);
+        }
+        let parts = s
+            .trim_end_matches(".*")
+            .split('.')
+            .map(|part| part.parse::<u32>().map_err(|_| invalid()))
+            .collect::<Result<Vec<u32>, PgEmbedError>>()?;
+        match parts[..] {
+            [major] => Ok(PgVersionReq::Major(major)),
+            [major, minor] => Ok(PgVersionReq::Minor(major, minor)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for PgVersionReq {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PgVersionReq::Latest => f.write_str("latest"),
+            PgVersionReq::Major(major) => write!(f, "{}.*", major),
+            PgVersionReq::Minor(major, minor) => write!(f, "{}.{}.*", major, minor),
+        }
+    }
+}
+
+impl Serialize for PgVersionReq {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for PgVersionReq {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let version_req = String::deserialize(deserializer)?;
+        version_req.parse().map_err(D::Error::custom)
+    }
+}
+
+///
+/// Release versions listed in a maven-metadata.xml document
+///
+/// Pre-release versions are skipped.
+///
+fn parse_maven_versions(metadata: &str) -> Vec<PostgresVersion> {
+    metadata
+        .split("<version>")
+        .skip(1)
+        .filter_map(|part| part.split("</version>").next())
+        .filter_map(|version| PostgresVersion::parse(version).ok())
+        .collect()
+}
+
+///
+/// Checksum verification of the downloaded postgresql binaries
+///
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum PgChecksum {
+    /// no verification
+    None,
+    /// verify against the `.sha256` file published alongside the binaries, if available
+    #[default]
+    Remote,
+    /// verify against the given sha256 hex digest
+    Sha256(String),
+}
+
+///
+/// Signature verification policy of the downloaded postgresql binaries
+///
+/// Signatures are verified against the `.asc` file published alongside the binaries
+/// by the `gpg` executable, which needs to be installed.
+///
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum PgTrustPolicy {
+    /// no signature verification
+    #[default]
+    None,
+    /// verify the signature if one is published
+    VerifyIfAvailable,
+    /// require a valid signature, fail if none is published
+    Required,
+}
+
+///
+/// Download progress of the postgresql binaries
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DownloadProgress {
+    /// received bytes
+    pub received: u64,
+    /// total bytes, if known
+    pub total: Option<u64>,
+}
+
+///
+/// Http(s) proxy used to fetch the postgresql binaries
+///
+/// Without an explicit proxy the `HTTP_PROXY`, `HTTPS_PROXY` and `NO_PROXY`
+/// environment variables are respected.
+///
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PgProxy {
+    /// proxy url, e.g. `http://proxy.example.com:3128`
+    pub url: String,
+    /// basic auth user name
+    pub username: Option<String>,
+    /// basic auth password
+    pub password: Option<String>,
+    /// comma separated hosts which bypass the proxy,
+    /// defaults to the `NO_PROXY` environment variable
+    pub no_proxy: Option<String>,
+}
+
+///
+/// Extension artifact installed into the postgresql binaries (e.g. pgvector or postgis builds)
+///
+/// The archive must mirror the installation layout, e.g. `lib/vector.so` and
+/// `share/extension/vector.control`. Any supported
+/// [ArchiveFormat](crate::pg_unpack::ArchiveFormat) may be used.
+///
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PgExtensionArtifact {
+    /// extension name, identifies the installed artifact in the cache
+    pub name: String,
+    /// download url of the extension archive
+    pub url: String,
+    /// expected sha256 hex digest of the archive
+    pub sha256: Option<String>,
+}
+
+///
+/// Disk budget of the pg-embed cache
+///
+/// Enforced after each acquisition by evicting the least recently used installations.
+///
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PgCacheBudget {
+    /// maximum number of cached installations
+    pub max_installations: Option<usize>,
+    /// maximum total size of the cached installations in bytes
+    pub max_size: Option<u64>,
+}
 
 /// Settings that determine the postgres binary to be fetched
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
 pub struct PgFetchSettings {
     /// The repository host
     pub host: String,
+    /// Mirror hosts of the repository (e.g. artifactory or nexus proxies),
+    /// tried in order if the download from the primary host fails
+    pub mirrors: Vec<String>,
     /// The operation system
     pub operating_system: OperationSystem,
     /// The cpu architecture
     pub architecture: Architecture,
+    /// Download url template of the binaries replacing the default repository layout,
+    /// e.g. `"{host}/postgres/{os}/{arch}/postgres-{version}.tar.xz"`
+    ///
+    /// Supported placeholders are `{host}`, `{platform}`, `{os}`, `{arch}` and `{version}`.
+    pub artifact_template: Option<String>,
     /// The postgresql version
+    ///
+    /// Replaced by the resolved version if [PgFetchSettings::version_req] is set.
     pub version: PostgresVersion,
+    /// Version requirement resolved against the repository (e.g. `"13"`, `"13.*"` or `"latest"`)
+    pub version_req: Option<PgVersionReq>,
+    /// Checksum verification of the downloaded binaries
+    pub checksum: PgChecksum,
+    /// Maximum number of download retries
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled on every further retry
+    #[serde(with = "humantime_serde")]
+    pub backoff: Duration,
+    /// Resume interrupted downloads with http range requests
+    pub resume: bool,
+    /// Timeout for establishing a connection to the repository
+    /// (only supported by the reqwest http client)
+    #[serde(with = "humantime_serde")]
+    pub connect_timeout: Option<Duration>,
+    /// Timeout of a single download attempt, retried like other download failures
+    #[serde(with = "humantime_serde")]
+    pub fetch_timeout: Option<Duration>,
+    /// Bandwidth limit of the download in bytes per second
+    pub max_bandwidth: Option<u64>,
+    /// Extension artifacts installed into the binaries cache directory
+    pub extensions: Vec<PgExtensionArtifact>,
+    /// Number of parallel range requests used to download the binaries,
+    /// falls back to a single stream if the server doesn't support range requests
+    pub download_concurrency: usize,
+    /// Explicit http(s) proxy
+    pub proxy: Option<PgProxy>,
+    /// Signature verification policy of the downloaded binaries
+    pub trust_policy: PgTrustPolicy,
+    /// Keyring with the trusted public keys, the default gpg keyring if not set
+    pub keyring: Option<PathBuf>,
+    /// Cache root directory of the binaries, defaults to the `PG_EMBED_CACHE_DIR`
+    /// environment variable or `pg-embed` in the user cache directory
+    pub cache_dir: Option<PathBuf>,
+    /// Remove the binaries archive after the extraction, otherwise it is kept in the cache directory
+    pub remove_archive: bool,
+    /// Disk budget of the cache, unlimited if not set
+    pub cache_budget: Option<PgCacheBudget>,
+    /// Custom binary provider used instead of downloading the binaries from the repository
+    /// (e.g. [LocalArchiveProvider](crate::pg_provider::LocalArchiveProvider))
+    #[serde(skip)]
+    pub provider: Option<Arc<dyn PgBinaryProvider>>,
+    /// Custom http client used instead of the built-in client of the enabled backend feature
+    /// (e.g. for mTLS to an internal mirror, custom headers or auth tokens)
+    #[serde(skip)]
+    pub http_client: Option<Arc<dyn PgHttpClient>>,
 }
 
 impl Default for PgFetchSettings {
     fn default() -> Self {
         PgFetchSettings {
             host: "https://repo1.maven.org".to_string(),
+            mirrors: Vec::new(),
             operating_system: OperationSystem::default(),
             architecture: Architecture::default(),
+            artifact_template: None,
             version: PG_V13,
+            version_req: None,
+            checksum: PgChecksum::default(),
+            max_retries: 3,
+            backoff: Duration::from_secs(1),
+            resume: true,
+            connect_timeout: Some(Duration::from_secs(30)),
+            fetch_timeout: None,
+            max_bandwidth: None,
+            download_concurrency: 1,
+            extensions: Vec::new(),
+            proxy: None,
+            trust_policy: PgTrustPolicy::default(),
+            keyring: None,
+            cache_dir: None,
+            remove_archive: true,
+            cache_budget: None,
+            provider: default_provider(),
+            http_client: None,
         }
     }
 }
 
+///
+/// The default binary provider
+///
+/// With the `bundled` feature the embedded archive is used instead of downloading the binaries.
+///
+fn default_provider() -> Option<Arc<dyn PgBinaryProvider>> {
+    #[cfg(feature = "bundled")]
+    {
+        Some(Arc::new(crate::pg_provider::BundledProvider))
+    }
+    #[cfg(not(feature = "bundled"))]
+    {
+        None
+    }
+}
+
+///
+/// The repository download provider
+///
+#[async_trait]
+impl PgBinaryProvider for PgFetchSettings {
+    fn cache_key(&self) -> String {
+        format!("{}/{}", self.platform(), self.version)
+    }
+
+    async fn fetch(&self, archive_path: &Path) -> PgResult<()> {
+        self.fetch_postgres(archive_path).await
+    }
+}
+
 impl PgFetchSettings {
+    ///
+    /// The binary provider
+    ///
+    /// Returns the custom [PgFetchSettings::provider] if set, otherwise the repository download provider.
+    ///
+    pub fn binary_provider(&self) -> &dyn PgBinaryProvider {
+        match &self.provider {
+            Some(provider) => provider.as_ref(),
+            None => self,
+        }
+    }
+
     /// The platform string (*needed to determine the download path*)
     pub fn platform(&self) -> String {
         let os = self.operating_system.to_string();
@@ -64,38 +526,874 @@ impl PgFetchSettings {
         format!("{}-{}", os, arch)
     }
 
+    ///
+    /// Resolve [PgFetchSettings::version_req] to the newest matching version published in the repository
+    ///
+    /// The resolved version is recorded in [PgFetchSettings::version].
+    /// Without a version requirement the configured version is kept.
+    ///
+    /// Returns the resolved version on success, otherwise returns an error.
+    ///
+    pub async fn resolve_version(&mut self) -> PgResult<&PostgresVersion> {
+        if let Some(version_req) = &self.version_req {
+            let version = self
+                .fetch_available_versions()
+                .await?
+                .into_iter()
+                .filter(|version| version_req.matches(version))
+                .max()
+                .ok_or_else(|| PgEmbedError {
+                    error_type: PgEmbedErrorType::InvalidConfig,
+                    source: None,
+                    message: Some(format!(
+                        "no published postgresql version matches {}",
+                        version_req
+                    )),
+                })?;
+            self.version = version;
+        }
+        Ok(&self.version)
+    }
+
+    ///
+    /// Fetch the postgresql versions published in the repository for the platform
+    ///
+    /// The mirrors are tried in order if the primary host fails.
+    ///
+    /// Returns the published versions on success, otherwise returns an error.
+    ///
+    pub async fn fetch_available_versions(&self) -> PgResult<Vec<PostgresVersion>> {
+        let mut result = Err(PgEmbedError {
+            error_type: PgEmbedErrorType::InvalidPgUrl,
+            source: None,
+            message: Some(String::from("no repository host")),
+        });
+        let client = self.client()?;
+        for host in std::iter::once(&self.host).chain(self.mirrors.iter()) {
+            result = self.fetch_maven_metadata(client.as_ref(), host).await;
+            match &result {
+                Ok(_) => break,
+                Err(e) => log::warn!(
+                    "fetching the postgresql versions from {} failed: {}",
+                    host,
+                    e
+                ),
+            }
+        }
+        result.map(|metadata| parse_maven_versions(&metadata))
+    }
+
+    ///
+    /// Fetch the maven-metadata.xml of the platform binaries from a repository host
+    ///
+    async fn fetch_maven_metadata(
+        &self,
+        client: &dyn PgHttpClient,
+        host: &str,
+    ) -> PgResult<String> {
+        let metadata_url = format!(
+            "{}/maven2/io/zonky/test/postgres/embedded-postgres-binaries-{}/maven-metadata.xml",
+            host,
+            self.platform()
+        );
+        let mut response = client.send(PgHttpRequest::get(&metadata_url)).await?;
+        pg_http::check_status(response.as_ref(), &metadata_url)?;
+        let body = pg_http::read_body(response.as_mut()).await?;
+        String::from_utf8(body).map_err(|e| PgEmbedError {
+            error_type: PgEmbedErrorType::ConversionFailure,
+            source: Some(Box::new(e)),
+            message: None,
+        })
+    }
+
     ///
     /// Fetch postgres binaries
     ///
-    /// Returns the data of the downloaded binary in an `Ok([u8])` on success, otherwise returns an error.
+    /// The archive is streamed to `archive_path`.
+    ///
+    /// Returns `Ok(())` on success, otherwise returns an error.
+    ///
+    pub async fn fetch_postgres(&self, archive_path: &Path) -> PgResult<()> {
+        self.fetch_postgres_with_progress(archive_path, &|_| {})
+            .await
+    }
+
+    ///
+    /// Fetch postgres binaries and report the download progress
+    ///
+    /// The archive is streamed to `archive_path`, `progress` is called after every received chunk.
+    /// Failed downloads are retried [PgFetchSettings::max_retries] times with exponential backoff,
+    /// before falling back to the next mirror.
+    ///
+    /// Returns `Ok(())` on success, otherwise returns an error.
+    ///
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(version = %self.version)))]
+    pub async fn fetch_postgres_with_progress(
+        &self,
+        archive_path: &Path,
+        progress: &(dyn Fn(DownloadProgress) + Send + Sync),
+    ) -> PgResult<()> {
+        // the repository layout only covers the detected operation systems
+        if let OperationSystem::Custom(os) = &self.operating_system {
+            if self.artifact_template.is_none() {
+                return Err(PgEmbedError {
+                    error_type: PgEmbedErrorType::UnsupportedPlatform,
+                    source: None,
+                    message: Some(format!(
+                        "no postgresql binaries are published for {}, \
+                         set an artifact template or a binary provider",
+                        os
+                    )),
+                });
+            }
+        }
+        let mut result = Err(PgEmbedError {
+            error_type: PgEmbedErrorType::InvalidPgUrl,
+            source: None,
+            message: Some(String::from("no repository host")),
+        });
+        let client = self.client()?;
+        for host in std::iter::once(&self.host).chain(self.mirrors.iter()) {
+            result = self
+                .fetch_postgres_from(client.as_ref(), host, archive_path, progress)
+                .await;
+            match &result {
+                Ok(_) => break,
+                Err(e) => log::warn!("fetching postgresql binaries from {} failed: {}", host, e),
+            }
+        }
+        result
+    }
+
+    ///
+    /// Fetch an extension artifact
+    ///
+    /// The archive is streamed to `archive_path` and verified against the expected digest, if set.
+    ///
+    /// Returns `Ok(())` on success, otherwise returns an error.
+    ///
+    pub async fn fetch_extension(
+        &self,
+        extension: &PgExtensionArtifact,
+        archive_path: &Path,
+    ) -> PgResult<()> {
+        let client = self.client()?;
+        let mut file = tokio::fs::File::create(archive_path)
+            .map_err(|e| PgEmbedError {
+                error_type: PgEmbedErrorType::WriteFileError,
+                source: Some(Box::new(e)),
+                message: None,
+            })
+            .await?;
+        self.download_with_retries(client.as_ref(), &extension.url, &mut file, &|_| {})
+            .await?;
+        drop(file);
+        match &extension.sha256 {
+            Some(expected) => check_sha256(&extension.url, archive_path, expected).await,
+            None => Ok(()),
+        }
+    }
+
+    ///
+    /// Fetch postgres binaries from a repository host
+    ///
+    async fn fetch_postgres_from(
+        &self,
+        client: &dyn PgHttpClient,
+        host: &str,
+        archive_path: &Path,
+        progress: &(dyn Fn(DownloadProgress) + Send + Sync),
+    ) -> PgResult<()> {
+        let download_url = self.download_url_for(host);
+        let mut file = tokio::fs::File::create(archive_path)
+            .map_err(|e| PgEmbedError {
+                error_type: PgEmbedErrorType::WriteFileError,
+                source: Some(Box::new(e)),
+                message: None,
+            })
+            .await?;
+        let parallel = if self.download_concurrency > 1 {
+            match self
+                .download_parallel(client, &download_url, archive_path, progress)
+                .await
+            {
+                Ok(parallel) => parallel,
+                Err(e) => {
+                    log::warn!(
+                        "parallel download of {} failed, falling back to a single stream: {}",
+                        download_url,
+                        e
+                    );
+                    truncate(&mut file).await?;
+                    false
+                }
+            }
+        } else {
+            false
+        };
+        if !parallel {
+            self.download_with_retries(client, &download_url, &mut file, progress)
+                .await?;
+        }
+        drop(file);
+
+        self.verify_checksum(client, &download_url, archive_path)
+            .await?;
+        self.verify_signature(client, &download_url, archive_path)
+            .await
+    }
+
+    ///
+    /// Download into `file`, retrying failed downloads with exponential backoff
+    ///
+    async fn download_with_retries(
+        &self,
+        client: &dyn PgHttpClient,
+        download_url: &str,
+        file: &mut tokio::fs::File,
+        progress: &(dyn Fn(DownloadProgress) + Send + Sync),
+    ) -> PgResult<()> {
+        let mut retries = 0;
+        loop {
+            match self
+                .download_with_timeout(client, download_url, file, progress)
+                .await
+            {
+                Ok(()) => return Ok(()),
+                Err(e) if retries < self.max_retries => {
+                    let delay = self.backoff.saturating_mul(2u32.saturating_pow(retries));
+                    retries += 1;
+                    log::warn!(
+                        "download of {} failed, retry {}/{} in {:?}: {}",
+                        download_url,
+                        retries,
+                        self.max_retries,
+                        delay,
+                        e
+                    );
+                    if !self.resume {
+                        truncate(file).await?;
+                    }
+                    tokio::time::sleep(delay).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    ///
+    /// Download into `archive_path` with [PgFetchSettings::download_concurrency] parallel range requests
+    ///
+    /// Returns `Ok(false)` without downloading if the server doesn't support range requests
+    /// or the archive is too small to be split, `Ok(true)` on success, otherwise returns an error.
+    ///
+    async fn download_parallel(
+        &self,
+        client: &dyn PgHttpClient,
+        download_url: &str,
+        archive_path: &Path,
+        progress: &(dyn Fn(DownloadProgress) + Send + Sync),
+    ) -> PgResult<bool> {
+        let probe = client
+            .send(PgHttpRequest::get(download_url).header("Range", "bytes=0-0"))
+            .await?;
+        if probe.status() != STATUS_PARTIAL_CONTENT {
+            return Ok(false);
+        }
+        // e.g. `bytes 0-0/12345`
+        let total = match probe.header("Content-Range").and_then(|range| {
+            range
+                .rsplit('/')
+                .next()
+                .map(|total| total.trim().parse::<u64>())
+        }) {
+            Some(Ok(total)) => total,
+            _ => return Ok(false),
+        };
+        drop(probe);
+        let concurrency = self.download_concurrency as u64;
+        if total < concurrency * MIN_PARALLEL_CHUNK_SIZE {
+            return Ok(false);
+        }
+
+        let file = open_for_write(archive_path).await?;
+        file.set_len(total)
+            .map_err(|e| PgEmbedError {
+                error_type: PgEmbedErrorType::WriteFileError,
+                source: Some(Box::new(e)),
+                message: None,
+            })
+            .await?;
+        drop(file);
+        progress(DownloadProgress {
+            received: 0,
+            total: Some(total),
+        });
+        let chunk_size = total / concurrency + 1;
+        let ranges: Vec<RangeInclusive<u64>> = (0..concurrency)
+            .map(|i| i * chunk_size..=((i + 1) * chunk_size).min(total) - 1)
+            .filter(|range| !range.is_empty())
+            .collect();
+        let download = PgRangeDownload {
+            client,
+            download_url,
+            archive_path,
+            received: AtomicU64::new(0),
+            total,
+            // the parallel downloads share the bandwidth limit
+            max_bandwidth: self
+                .max_bandwidth
+                .filter(|limit| *limit > 0)
+                .map(|limit| (limit / ranges.len() as u64).max(1)),
+            progress,
+        };
+        let downloads = ranges
+            .into_iter()
+            .map(|range| self.download_range_with_retries(&download, range));
+        futures::future::try_join_all(downloads).await?;
+        Ok(true)
+    }
+
+    ///
+    /// Download a byte range like [PgFetchSettings::download_with_retries]
     ///
-    pub async fn fetch_postgres(&self) -> PgResult<Box<Bytes>> {
+    /// Every attempt is limited by [PgFetchSettings::fetch_timeout], failed attempts are
+    /// retried [PgFetchSettings::max_retries] times with exponential backoff and resume
+    /// the range if [PgFetchSettings::resume] is set.
+    ///
+    async fn download_range_with_retries(
+        &self,
+        download: &PgRangeDownload<'_>,
+        range: RangeInclusive<u64>,
+    ) -> PgResult<()> {
+        let download_url = download.download_url;
+        let mut written = 0u64;
+        let mut retries = 0;
+        loop {
+            let attempt = self.download_range(download, &range, &mut written);
+            let result = match self.fetch_timeout {
+                Some(fetch_timeout) => tokio::time::timeout(fetch_timeout, attempt)
+                    .await
+                    .unwrap_or_else(|e| {
+                        Err(PgEmbedError {
+                            error_type: PgEmbedErrorType::DownloadFailure,
+                            source: Some(Box::new(e)),
+                            message: Some(format!(
+                                "download of {} timed out after {:?}",
+                                download_url, fetch_timeout
+                            )),
+                        })
+                    }),
+                None => attempt.await,
+            };
+            match result {
+                Ok(()) => return Ok(()),
+                Err(e) if retries < self.max_retries => {
+                    let delay = self.backoff.saturating_mul(2u32.saturating_pow(retries));
+                    retries += 1;
+                    log::warn!(
+                        "download of {} bytes {}-{} failed, retry {}/{} in {:?}: {}",
+                        download_url,
+                        range.start(),
+                        range.end(),
+                        retries,
+                        self.max_retries,
+                        delay,
+                        e
+                    );
+                    if !self.resume {
+                        download.received.fetch_sub(written, Ordering::SeqCst);
+                        written = 0;
+                    }
+                    tokio::time::sleep(delay).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    ///
+    /// Download a byte range into the preallocated `archive_path`
+    ///
+    /// The request starts after the `written` bytes of previous attempts, which are updated
+    /// with the bytes written by this attempt.
+    ///
+    async fn download_range(
+        &self,
+        download: &PgRangeDownload<'_>,
+        range: &RangeInclusive<u64>,
+        written: &mut u64,
+    ) -> PgResult<()> {
+        let download_url = download.download_url;
+        let start = range.start() + *written;
+        if start > *range.end() {
+            return Ok(());
+        }
+        let mut response = download
+            .client
+            .send(
+                PgHttpRequest::get(download_url)
+                    .header("Range", format!("bytes={}-{}", start, range.end())),
+            )
+            .await?;
+        pg_http::check_status(response.as_ref(), download_url)?;
+        if response.status() != STATUS_PARTIAL_CONTENT {
+            return Err(PgEmbedError {
+                error_type: PgEmbedErrorType::DownloadFailure,
+                source: None,
+                message: Some(format!("range requests not supported by {}", download_url)),
+            });
+        }
+        let mut file = open_for_write(download.archive_path).await?;
+        file.seek(SeekFrom::Start(start))
+            .map_err(|e| PgEmbedError {
+                error_type: PgEmbedErrorType::WriteFileError,
+                source: Some(Box::new(e)),
+                message: None,
+            })
+            .await?;
+        let mut remaining = range.end() - start + 1;
+        let started = Instant::now();
+        let mut attempt_received = 0u64;
+        while let Some(chunk) = response.chunk().await? {
+            let len = chunk.len() as u64;
+            if len > remaining {
+                return Err(PgEmbedError {
+                    error_type: PgEmbedErrorType::DownloadFailure,
+                    source: None,
+                    message: Some(format!("unexpected range length from {}", download_url)),
+                });
+            }
+            file.write_all(&chunk)
+                .map_err(|e| PgEmbedError {
+                    error_type: PgEmbedErrorType::WriteFileError,
+                    source: Some(Box::new(e)),
+                    message: None,
+                })
+                .await?;
+            remaining -= len;
+            *written += len;
+            (download.progress)(DownloadProgress {
+                received: download.received.fetch_add(len, Ordering::SeqCst) + len,
+                total: Some(download.total),
+            });
+            attempt_received += len;
+            throttle(started, attempt_received, download.max_bandwidth).await;
+        }
+        if remaining > 0 {
+            return Err(PgEmbedError {
+                error_type: PgEmbedErrorType::DownloadFailure,
+                source: None,
+                message: Some(format!("incomplete range from {}", download_url)),
+            });
+        }
+        file.flush()
+            .map_err(|e| PgEmbedError {
+                error_type: PgEmbedErrorType::WriteFileError,
+                source: Some(Box::new(e)),
+                message: None,
+            })
+            .await
+    }
+
+    ///
+    /// Download into `file`, failing after [PgFetchSettings::fetch_timeout]
+    ///
+    async fn download_with_timeout(
+        &self,
+        client: &dyn PgHttpClient,
+        download_url: &str,
+        file: &mut tokio::fs::File,
+        progress: &(dyn Fn(DownloadProgress) + Send + Sync),
+    ) -> PgResult<()> {
+        let download = self.download(client, download_url, file, progress);
+        match self.fetch_timeout {
+            Some(fetch_timeout) => {
+                tokio::time::timeout(fetch_timeout, download)
+                    .map_err(|e| PgEmbedError {
+                        error_type: PgEmbedErrorType::DownloadFailure,
+                        source: Some(Box::new(e)),
+                        message: Some(format!(
+                            "download of {} timed out after {:?}",
+                            download_url, fetch_timeout
+                        )),
+                    })
+                    .await?
+            }
+            None => download.await,
+        }
+    }
+
+    ///
+    /// Download into `file`
+    ///
+    /// If `file` already holds data of a previous attempt, only the remaining bytes are requested.
+    ///
+    async fn download(
+        &self,
+        client: &dyn PgHttpClient,
+        download_url: &str,
+        file: &mut tokio::fs::File,
+        progress: &(dyn Fn(DownloadProgress) + Send + Sync),
+    ) -> PgResult<()> {
+        let mut received = file
+            .metadata()
+            .map_err(|e| PgEmbedError {
+                error_type: PgEmbedErrorType::ReadFileError,
+                source: Some(Box::new(e)),
+                message: None,
+            })
+            .await?
+            .len();
+        let mut request = PgHttpRequest::get(download_url);
+        if received > 0 {
+            request = request.header("Range", format!("bytes={}-", received));
+        }
+        let mut response = client.send(request).await?;
+        if response.status() == STATUS_RANGE_NOT_SATISFIABLE {
+            // restart the download on the next attempt
+            truncate(file).await?;
+        }
+        pg_http::check_status(response.as_ref(), download_url)?;
+        if received > 0 && response.status() != STATUS_PARTIAL_CONTENT {
+            // the server doesn't support range requests
+            truncate(file).await?;
+            received = 0;
+        }
+
+        let total = response.content_length().map(|length| length + received);
+        progress(DownloadProgress { received, total });
+        let started = Instant::now();
+        let mut attempt_received = 0u64;
+        while let Some(chunk) = response.chunk().await? {
+            file.write_all(&chunk)
+                .map_err(|e| PgEmbedError {
+                    error_type: PgEmbedErrorType::WriteFileError,
+                    source: Some(Box::new(e)),
+                    message: None,
+                })
+                .await?;
+            received += chunk.len() as u64;
+            progress(DownloadProgress { received, total });
+            attempt_received += chunk.len() as u64;
+            throttle(
+                started,
+                attempt_received,
+                self.max_bandwidth.filter(|limit| *limit > 0),
+            )
+            .await;
+        }
+        file.flush()
+            .map_err(|e| PgEmbedError {
+                error_type: PgEmbedErrorType::WriteFileError,
+                source: Some(Box::new(e)),
+                message: None,
+            })
+            .await
+    }
+
+    ///
+    /// The http client used to fetch the binaries
+    ///
+    /// Returns the custom [PgFetchSettings::http_client] if set, otherwise the built-in client.
+    ///
+    fn client(&self) -> PgResult<Arc<dyn PgHttpClient>> {
+        match &self.http_client {
+            Some(client) => Ok(client.clone()),
+            None => pg_http::default_client(self),
+        }
+    }
+
+    ///
+    /// The download url of the postgresql binaries
+    ///
+    pub fn download_url(&self) -> String {
+        self.download_url_for(&self.host)
+    }
+
+    ///
+    /// The download url of the postgresql binaries on a repository host
+    ///
+    fn download_url_for(&self, host: &str) -> String {
         let platform = &self.platform();
-        let version = self.version.0;
-        let download_url = format!(
+        let version = self.version.to_string();
+        if let Some(template) = &self.artifact_template {
+            return template
+                .replace("{host}", host)
+                .replace("{platform}", platform)
+                .replace("{os}", &self.operating_system.to_string())
+                .replace("{arch}", &self.architecture.to_string())
+                .replace("{version}", &version);
+        }
+        format!(
             "{}/maven2/io/zonky/test/postgres/embedded-postgres-binaries-{}/{}/embedded-postgres-binaries-{}-{}.jar",
-            &self.host,
+            host,
             &platform,
             version,
             &platform,
-            version);
-        let response: Response = reqwest::get(download_url)
+            version)
+    }
+
+    ///
+    /// Verify the checksum of the downloaded binaries
+    ///
+    /// Returns `Ok(())` if the checksum matches or no checksum is available,
+    /// otherwise returns an error.
+    ///
+    async fn verify_checksum(
+        &self,
+        client: &dyn PgHttpClient,
+        download_url: &str,
+        archive_path: &Path,
+    ) -> PgResult<()> {
+        let expected = match &self.checksum {
+            PgChecksum::None => return Ok(()),
+            PgChecksum::Sha256(digest) => digest.clone(),
+            PgChecksum::Remote => match self.fetch_remote_checksum(client, download_url).await? {
+                Some(digest) => digest,
+                None => {
+                    log::warn!("no checksum published for {}", download_url);
+                    return Ok(());
+                }
+            },
+        };
+        check_sha256(download_url, archive_path, &expected).await
+    }
+
+    ///
+    /// Verify the signature of the downloaded binaries according to the [PgTrustPolicy]
+    ///
+    /// Returns `Ok(())` if the signature is valid or not required, otherwise returns an error.
+    ///
+    async fn verify_signature(
+        &self,
+        client: &dyn PgHttpClient,
+        download_url: &str,
+        archive_path: &Path,
+    ) -> PgResult<()> {
+        if self.trust_policy == PgTrustPolicy::None {
+            return Ok(());
+        }
+        let signature = match self.fetch_signature(client, download_url).await? {
+            Some(signature) => signature,
+            None if self.trust_policy == PgTrustPolicy::Required => {
+                return Err(PgEmbedError {
+                    error_type: PgEmbedErrorType::InvalidSignature,
+                    source: None,
+                    message: Some(format!("no signature published for {}", download_url)),
+                })
+            }
+            None => {
+                log::warn!("no signature published for {}", download_url);
+                return Ok(());
+            }
+        };
+        let signature_path = archive_path.with_extension("asc");
+        tokio::fs::write(&signature_path, &signature)
             .map_err(|e| PgEmbedError {
-                error_type: PgEmbedErrorType::DownloadFailure,
+                error_type: PgEmbedErrorType::WriteFileError,
                 source: Some(Box::new(e)),
                 message: None,
             })
             .await?;
+        let mut command = tokio::process::Command::new("gpg");
+        command.arg("--batch");
+        if let Some(keyring) = &self.keyring {
+            command
+                .arg("--no-default-keyring")
+                .arg("--keyring")
+                .arg(keyring);
+        }
+        let output = command
+            .arg("--verify")
+            .arg(&signature_path)
+            .arg(archive_path)
+            .output()
+            .await;
+        let _ = tokio::fs::remove_file(&signature_path).await;
+        let output = output.map_err(|e| PgEmbedError {
+            error_type: PgEmbedErrorType::InvalidSignature,
+            source: Some(Box::new(e)),
+            message: Some(String::from("could not execute gpg")),
+        })?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(PgEmbedError {
+                error_type: PgEmbedErrorType::InvalidSignature,
+                source: None,
+                message: Some(format!(
+                    "invalid signature for {}: {}",
+                    download_url,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                )),
+            })
+        }
+    }
+
+    ///
+    /// Fetch the signature published alongside the binaries
+    ///
+    /// Returns `Ok(None)` if no signature is published.
+    ///
+    async fn fetch_signature(
+        &self,
+        client: &dyn PgHttpClient,
+        download_url: &str,
+    ) -> PgResult<Option<Vec<u8>>> {
+        let signature_url = format!("{}.asc", download_url);
+        let mut response = client.send(PgHttpRequest::get(&signature_url)).await?;
+        if response.status() == STATUS_NOT_FOUND {
+            return Ok(None);
+        }
+        pg_http::check_status(response.as_ref(), &signature_url)?;
+        Ok(Some(pg_http::read_body(response.as_mut()).await?))
+    }
+
+    ///
+    /// Fetch the sha256 digest published alongside the binaries
+    ///
+    /// Returns `Ok(None)` if no digest is published.
+    ///
+    async fn fetch_remote_checksum(
+        &self,
+        client: &dyn PgHttpClient,
+        download_url: &str,
+    ) -> PgResult<Option<String>> {
+        let checksum_url = format!("{}.sha256", download_url);
+        let mut response = client.send(PgHttpRequest::get(&checksum_url)).await?;
+        if response.status() == STATUS_NOT_FOUND {
+            return Ok(None);
+        }
+        pg_http::check_status(response.as_ref(), &checksum_url)?;
+        let content =
+            String::from_utf8_lossy(&pg_http::read_body(response.as_mut()).await?).into_owned();
+        // the file may contain the digest followed by the file name
+        Ok(content
+            .split_whitespace()
+            .next()
+            .map(|digest| digest.to_string()))
+    }
+}
+
+///
+/// Compare the sha256 digest of a downloaded file with the expected hex digest
+///
+async fn check_sha256(download_url: &str, file_path: &Path, expected: &str) -> PgResult<()> {
+    let actual = file_sha256(file_path).await?;
+    if expected.trim().eq_ignore_ascii_case(&actual) {
+        Ok(())
+    } else {
+        Err(PgEmbedError {
+            error_type: PgEmbedErrorType::ChecksumMismatch,
+            source: None,
+            message: Some(format!(
+                "checksum mismatch for {}: expected {}, got {}",
+                download_url,
+                expected.trim(),
+                actual
+            )),
+        })
+    }
+}
+
+///
+/// Open an existing file for writing
+///
+async fn open_for_write(file_path: &Path) -> PgResult<tokio::fs::File> {
+    tokio::fs::OpenOptions::new()
+        .write(true)
+        .open(file_path)
+        .map_err(|e| PgEmbedError {
+            error_type: PgEmbedErrorType::WriteFileError,
+            source: Some(Box::new(e)),
+            message: None,
+        })
+        .await
+}
+
+///
+/// Shared state of the parallel range downloads of an archive
+///
+struct PgRangeDownload<'a> {
+    /// http client
+    client: &'a dyn PgHttpClient,
+    /// archive url
+    download_url: &'a str,
+    /// preallocated archive file
+    archive_path: &'a Path,
+    /// bytes received by all range downloads
+    received: AtomicU64,
+    /// archive size
+    total: u64,
+    /// bandwidth limit of each range download
+    max_bandwidth: Option<u64>,
+    /// progress callback
+    progress: &'a (dyn Fn(DownloadProgress) + Send + Sync),
+}
+
+///
+/// Sleep until the bytes received since `started` are within the bandwidth limit
+///
+async fn throttle(started: Instant, received: u64, max_bandwidth: Option<u64>) {
+    if let Some(max_bandwidth) = max_bandwidth {
+        let expected = Duration::from_secs_f64(received as f64 / max_bandwidth as f64);
+        let elapsed = started.elapsed();
+        if expected > elapsed {
+            tokio::time::sleep(expected - elapsed).await;
+        }
+    }
+}
 
-        let content: Bytes = response
-            .bytes()
+///
+/// Truncate a partially downloaded file
+///
+async fn truncate(file: &mut tokio::fs::File) -> PgResult<()> {
+    file.set_len(0)
+        .map_err(|e| PgEmbedError {
+            error_type: PgEmbedErrorType::WriteFileError,
+            source: Some(Box::new(e)),
+            message: None,
+        })
+        .await?;
+    file.seek(SeekFrom::Start(0))
+        .map_err(|e| PgEmbedError {
+            error_type: PgEmbedErrorType::WriteFileError,
+            source: Some(Box::new(e)),
+            message: None,
+        })
+        .await?;
+    Ok(())
+}
+
+///
+/// Hex encoded sha256 digest of a file
+///
+/// The file is read in chunks to keep the memory usage bounded.
+///
+async fn file_sha256(file_path: &Path) -> PgResult<String> {
+    let mut file = tokio::fs::File::open(file_path)
+        .map_err(|e| PgEmbedError {
+            error_type: PgEmbedErrorType::ReadFileError,
+            source: Some(Box::new(e)),
+            message: None,
+        })
+        .await?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let len = file
+            .read(&mut buffer)
             .map_err(|e| PgEmbedError {
-                error_type: PgEmbedErrorType::ConversionFailure,
+                error_type: PgEmbedErrorType::ReadFileError,
                 source: Some(Box::new(e)),
                 message: None,
             })
             .await?;
-
-        Ok(Box::new(content))
+        if len == 0 {
+            break;
+        }
+        hasher.update(&buffer[..len]);
     }
+    Ok(hex::encode(hasher.finalize()))
 }
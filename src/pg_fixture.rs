@@ -0,0 +1,150 @@
+//!
+//! Test data fixtures
+//!
+//! Reusable, structured test data setup applied with [PgEmbed::apply_fixtures].
+//!
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+
+use crate::pg_seed::PgSeedSpec;
+use crate::pg_types::PgResult;
+use crate::postgres::{PgCsvOptions, PgEmbed};
+
+///
+/// Test data applied to a database
+///
+#[async_trait]
+pub trait PgFixture: Send + Sync {
+    ///
+    /// Apply the fixture to the database `db_name`
+    ///
+    /// Returns `Ok(())` on success, otherwise returns an error.
+    ///
+    async fn apply(&self, pg: &PgEmbed, db_name: &str) -> PgResult<()>;
+}
+
+///
+/// Fixture executing sql statements
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgSqlFixture {
+    /// sql statements
+    pub sql: String,
+}
+
+impl PgSqlFixture {
+    ///
+    /// Create a new fixture executing `sql`
+    ///
+    pub fn new<S: Into<String>>(sql: S) -> Self {
+        PgSqlFixture { sql: sql.into() }
+    }
+}
+
+#[async_trait]
+impl PgFixture for PgSqlFixture {
+    async fn apply(&self, pg: &PgEmbed, db_name: &str) -> PgResult<()> {
+        pg.psql_exec(db_name, &self.sql).await?;
+        Ok(())
+    }
+}
+
+///
+/// Fixture executing a sql script file
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgSqlFileFixture {
+    /// sql script file
+    pub path: PathBuf,
+}
+
+impl PgSqlFileFixture {
+    ///
+    /// Create a new fixture executing the script file at `path`
+    ///
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        PgSqlFileFixture { path: path.into() }
+    }
+}
+
+#[async_trait]
+impl PgFixture for PgSqlFileFixture {
+    async fn apply(&self, pg: &PgEmbed, db_name: &str) -> PgResult<()> {
+        let path = self.path.to_string_lossy().to_string();
+        pg.run_client_tool(
+            "psql",
+            &[
+                "--no-psqlrc",
+                "-v",
+                "ON_ERROR_STOP=1",
+                "-1",
+                "-d",
+                db_name,
+                "-f",
+                &path,
+            ],
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+///
+/// Fixture loading a csv file into a table with COPY
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgCsvFixture {
+    /// table name, may be schema qualified
+    pub table: String,
+    /// csv file
+    pub path: PathBuf,
+    /// csv format options
+    pub options: PgCsvOptions,
+}
+
+impl PgCsvFixture {
+    ///
+    /// Create a new fixture loading the csv file at `path` into `table`
+    ///
+    pub fn new<S: Into<String>, P: Into<PathBuf>>(table: S, path: P) -> Self {
+        PgCsvFixture {
+            table: table.into(),
+            path: path.into(),
+            options: PgCsvOptions::default(),
+        }
+    }
+}
+
+#[async_trait]
+impl PgFixture for PgCsvFixture {
+    async fn apply(&self, pg: &PgEmbed, db_name: &str) -> PgResult<()> {
+        pg.copy_from_csv(db_name, &self.table, &self.path, &self.options)
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl PgFixture for PgSeedSpec {
+    async fn apply(&self, pg: &PgEmbed, db_name: &str) -> PgResult<()> {
+        pg.seed(db_name, self).await?;
+        Ok(())
+    }
+}
+
+impl PgEmbed {
+    ///
+    /// Apply fixtures to a database in order
+    ///
+    /// Stops at the first failing fixture.
+    ///
+    /// Returns `Ok(())` on success, otherwise returns an error.
+    ///
+    pub async fn apply_fixtures(&self, db_name: &str, fixtures: &[&dyn PgFixture]) -> PgResult<()> {
+        for fixture in fixtures {
+            fixture.apply(self, db_name).await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,367 @@
+//!
+//! Http client backends
+//!
+//! The postgresql binaries are fetched with a [PgHttpClient].
+//! Built-in clients are provided by the `http_reqwest` and `http_surf` features,
+//! a custom client can be set in [PgFetchSettings::http_client]
+//! (e.g. for mTLS to an internal mirror, custom headers or auth tokens).
+//!
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::TryFutureExt;
+
+use crate::pg_errors::{PgEmbedError, PgEmbedErrorType};
+use crate::pg_fetch::PgFetchSettings;
+use crate::pg_types::PgResult;
+
+/// Http status code `206 Partial Content`
+pub const STATUS_PARTIAL_CONTENT: u16 = 206;
+/// Http status code `404 Not Found`
+pub const STATUS_NOT_FOUND: u16 = 404;
+/// Http status code `416 Range Not Satisfiable`
+pub const STATUS_RANGE_NOT_SATISFIABLE: u16 = 416;
+
+///
+/// Http GET request
+///
+#[derive(Debug, Clone)]
+pub struct PgHttpRequest {
+    /// request url
+    pub url: String,
+    /// request headers
+    pub headers: Vec<(String, String)>,
+}
+
+impl PgHttpRequest {
+    ///
+    /// Create a GET request for `url`
+    ///
+    pub fn get<S: Into<String>>(url: S) -> Self {
+        PgHttpRequest {
+            url: url.into(),
+            headers: Vec::new(),
+        }
+    }
+
+    ///
+    /// Add a request header
+    ///
+    pub fn header<N: Into<String>, V: Into<String>>(mut self, name: N, value: V) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+}
+
+///
+/// Http response with a streamed body
+///
+#[async_trait]
+pub trait PgHttpResponse: Send {
+    ///
+    /// Http status code
+    ///
+    fn status(&self) -> u16;
+
+    ///
+    /// Content length of the response body, if known
+    ///
+    fn content_length(&self) -> Option<u64>;
+
+    ///
+    /// Value of a response header, if present
+    ///
+    /// Parallel downloads need the `Content-Range` header, the default implementation
+    /// returns `None` which disables them.
+    ///
+    fn header(&self, _name: &str) -> Option<String> {
+        None
+    }
+
+    ///
+    /// Next chunk of the response body, `None` at the end of the body
+    ///
+    async fn chunk(&mut self) -> PgResult<Option<Bytes>>;
+}
+
+///
+/// Http client used to fetch the postgresql binaries
+///
+#[async_trait]
+pub trait PgHttpClient: Send + Sync {
+    ///
+    /// Send a request
+    ///
+    /// Returns the response on success, otherwise returns an error.
+    /// Error status codes are returned as response.
+    ///
+    async fn send(&self, request: PgHttpRequest) -> PgResult<Box<dyn PgHttpResponse>>;
+}
+
+///
+/// Fail on error status codes (>= 400)
+///
+pub fn check_status(response: &dyn PgHttpResponse, url: &str) -> PgResult<()> {
+    if response.status() >= 400 {
+        Err(PgEmbedError {
+            error_type: PgEmbedErrorType::DownloadFailure,
+            source: None,
+            message: Some(format!(
+                "request to {} failed with status {}",
+                url,
+                response.status()
+            )),
+        })
+    } else {
+        Ok(())
+    }
+}
+
+///
+/// Read the whole response body
+///
+pub async fn read_body(response: &mut dyn PgHttpResponse) -> PgResult<Vec<u8>> {
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+///
+/// The built-in http client of the enabled backend feature
+///
+/// `http_reqwest` is preferred if both backend features are enabled.
+///
+pub fn default_client(fetch_settings: &PgFetchSettings) -> PgResult<Arc<dyn PgHttpClient>> {
+    #[cfg(feature = "http_reqwest")]
+    {
+        ReqwestClient::new(fetch_settings).map(|client| Arc::new(client) as Arc<dyn PgHttpClient>)
+    }
+    #[cfg(all(feature = "http_surf", not(feature = "http_reqwest")))]
+    {
+        SurfClient::new(fetch_settings).map(|client| Arc::new(client) as Arc<dyn PgHttpClient>)
+    }
+    #[cfg(not(any(feature = "http_reqwest", feature = "http_surf")))]
+    {
+        let _ = fetch_settings;
+        Err(PgEmbedError {
+            error_type: PgEmbedErrorType::InvalidConfig,
+            source: None,
+            message: Some(String::from(
+                "no http client available, enable the http_reqwest or http_surf feature or set a custom client",
+            )),
+        })
+    }
+}
+
+///
+/// Http client backed by reqwest
+///
+#[cfg(feature = "http_reqwest")]
+pub struct ReqwestClient {
+    client: reqwest::Client,
+}
+
+#[cfg(feature = "http_reqwest")]
+impl ReqwestClient {
+    ///
+    /// Create a new client with the proxy and connect timeout of the fetch settings
+    ///
+    pub fn new(fetch_settings: &PgFetchSettings) -> PgResult<Self> {
+        let mut builder = reqwest::Client::builder();
+        if let Some(connect_timeout) = fetch_settings.connect_timeout {
+            builder = builder.connect_timeout(connect_timeout);
+        }
+        if let Some(proxy) = &fetch_settings.proxy {
+            let mut client_proxy = reqwest::Proxy::all(&proxy.url).map_err(|e| PgEmbedError {
+                error_type: PgEmbedErrorType::InvalidConfig,
+                source: Some(Box::new(e)),
+                message: Some(format!("invalid proxy url {}", proxy.url)),
+            })?;
+            if let Some(username) = &proxy.username {
+                client_proxy =
+                    client_proxy.basic_auth(username, proxy.password.as_deref().unwrap_or(""));
+            }
+            let no_proxy = match &proxy.no_proxy {
+                Some(no_proxy) => reqwest::NoProxy::from_string(no_proxy),
+                None => reqwest::NoProxy::from_env(),
+            };
+            builder = builder.proxy(client_proxy.no_proxy(no_proxy));
+        }
+        let client = builder.build().map_err(|e| PgEmbedError {
+            error_type: PgEmbedErrorType::DownloadFailure,
+            source: Some(Box::new(e)),
+            message: None,
+        })?;
+        Ok(ReqwestClient { client })
+    }
+
+    ///
+    /// Wrap a preconfigured reqwest client (e.g. with client certificates or default headers)
+    ///
+    pub fn from_client(client: reqwest::Client) -> Self {
+        ReqwestClient { client }
+    }
+}
+
+#[cfg(feature = "http_reqwest")]
+#[async_trait]
+impl PgHttpClient for ReqwestClient {
+    async fn send(&self, request: PgHttpRequest) -> PgResult<Box<dyn PgHttpResponse>> {
+        let mut builder = self.client.get(&request.url);
+        for (name, value) in &request.headers {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+        let response = builder
+            .send()
+            .map_err(|e| PgEmbedError {
+                error_type: PgEmbedErrorType::DownloadFailure,
+                source: Some(Box::new(e)),
+                message: None,
+            })
+            .await?;
+        Ok(Box::new(ReqwestResponse(response)))
+    }
+}
+
+///
+/// Response of the reqwest client
+///
+#[cfg(feature = "http_reqwest")]
+struct ReqwestResponse(reqwest::Response);
+
+#[cfg(feature = "http_reqwest")]
+#[async_trait]
+impl PgHttpResponse for ReqwestResponse {
+    fn status(&self) -> u16 {
+        self.0.status().as_u16()
+    }
+
+    fn content_length(&self) -> Option<u64> {
+        self.0.content_length()
+    }
+
+    fn header(&self, name: &str) -> Option<String> {
+        self.0
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string())
+    }
+
+    async fn chunk(&mut self) -> PgResult<Option<Bytes>> {
+        self.0
+            .chunk()
+            .map_err(|e| PgEmbedError {
+                error_type: PgEmbedErrorType::ConversionFailure,
+                source: Some(Box::new(e)),
+                message: None,
+            })
+            .await
+    }
+}
+
+///
+/// Http client backed by surf
+///
+/// Proxies are not supported by this client.
+///
+#[cfg(feature = "http_surf")]
+pub struct SurfClient {
+    client: surf::Client,
+}
+
+#[cfg(feature = "http_surf")]
+impl SurfClient {
+    ///
+    /// Create a new client
+    ///
+    /// Returns an error if a proxy is configured in the fetch settings.
+    ///
+    pub fn new(fetch_settings: &PgFetchSettings) -> PgResult<Self> {
+        if fetch_settings.proxy.is_some() {
+            return Err(PgEmbedError {
+                error_type: PgEmbedErrorType::InvalidConfig,
+                source: None,
+                message: Some(String::from(
+                    "proxies are not supported by the surf http client",
+                )),
+            });
+        }
+        Ok(SurfClient {
+            client: surf::Client::new(),
+        })
+    }
+
+    ///
+    /// Wrap a preconfigured surf client
+    ///
+    pub fn from_client(client: surf::Client) -> Self {
+        SurfClient { client }
+    }
+}
+
+#[cfg(feature = "http_surf")]
+#[async_trait]
+impl PgHttpClient for SurfClient {
+    async fn send(&self, request: PgHttpRequest) -> PgResult<Box<dyn PgHttpResponse>> {
+        let mut builder = self.client.get(&request.url);
+        for (name, value) in &request.headers {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+        let response = builder
+            .map_err(|e| PgEmbedError {
+                error_type: PgEmbedErrorType::DownloadFailure,
+                source: Some(e.into_inner().into()),
+                message: None,
+            })
+            .await?;
+        Ok(Box::new(SurfResponse(response)))
+    }
+}
+
+///
+/// Response of the surf client
+///
+#[cfg(feature = "http_surf")]
+struct SurfResponse(surf::Response);
+
+#[cfg(feature = "http_surf")]
+#[async_trait]
+impl PgHttpResponse for SurfResponse {
+    fn status(&self) -> u16 {
+        self.0.status().into()
+    }
+
+    fn content_length(&self) -> Option<u64> {
+        self.0.len().map(|len| len as u64)
+    }
+
+    fn header(&self, name: &str) -> Option<String> {
+        self.0
+            .header(name)
+            .map(|values| values.last().as_str().to_string())
+    }
+
+    async fn chunk(&mut self) -> PgResult<Option<Bytes>> {
+        use futures::AsyncReadExt;
+        let mut buffer = vec![0u8; 64 * 1024];
+        let len = self
+            .0
+            .read(&mut buffer)
+            .map_err(|e| PgEmbedError {
+                error_type: PgEmbedErrorType::ConversionFailure,
+                source: Some(Box::new(e)),
+                message: None,
+            })
+            .await?;
+        if len == 0 {
+            Ok(None)
+        } else {
+            buffer.truncate(len);
+            Ok(Some(Bytes::from(buffer)))
+        }
+    }
+}
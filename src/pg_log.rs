@@ -0,0 +1,606 @@
+//!
+//! Server log
+//!
+//! Structured server log events and statement capture.
+//!
+//! With [PgSettings::structured_log](crate::postgres::PgSettings::structured_log) the server
+//! logs in csv format to the `pg_embed_log` directory of the database cluster.
+//! [PgEmbed::log_watcher] tails the log and parses the entries into [PgLogEvent]s.
+//! The csv log is used for all postgresql versions, its leading columns are stable.
+//!
+//! With [PgSettings::capture_statements](crate::postgres::PgSettings::capture_statements)
+//! the server additionally logs every statement (`log_statement = all`).
+//! [PgEmbed::captured_statements] parses the log into [PgCapturedStatement]s, e.g. to print
+//! the sql traffic after a failed test.
+//!
+//! With [PgSettings::slow_query_threshold](crate::postgres::PgSettings::slow_query_threshold)
+//! the server logs slow statements and their plans (`auto_explain`).
+//! [PgEmbed::slow_query_report] returns the slowest statements of a test run.
+//!
+//! The current log files are looked up in the `current_logfiles` file of the database
+//! cluster (postgresql versions >= 10), so rotated and explicitly configured log files
+//! ([PgLogConfig](crate::pg_config::PgLogConfig)) are followed.
+//!
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use futures::{Stream, TryFutureExt};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use crate::pg_enums::PgLogLevel;
+use crate::pg_errors::{PgEmbedError, PgEmbedErrorType};
+use crate::pg_types::PgResult;
+use crate::postgres::PgEmbed;
+
+/// Log directory, relative to the database directory
+pub const PG_EMBED_LOG_DIR_NAME: &str = "pg_embed_log";
+/// Log file name, postgresql replaces the `.log` suffix of csv logs with `.csv`
+const PG_EMBED_LOG_FILE_NAME: &str = "postgresql.log";
+/// File listing the current log file of each log destination
+const CURRENT_LOG_FILES_NAME: &str = "current_logfiles";
+
+/// csv log column of the log time
+const CSV_LOG_TIME: usize = 0;
+/// csv log column of the user name
+const CSV_USER_NAME: usize = 1;
+/// csv log column of the database name
+const CSV_DATABASE_NAME: usize = 2;
+/// csv log column of the backend process id
+const CSV_PROCESS_ID: usize = 3;
+/// csv log column of the severity
+const CSV_SEVERITY: usize = 11;
+/// csv log column of the sqlstate code
+const CSV_SQLSTATE: usize = 12;
+/// csv log column of the message
+const CSV_MESSAGE: usize = 13;
+/// csv log column of the message detail
+const CSV_DETAIL: usize = 14;
+
+///
+/// Statement executed by the server
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgCapturedStatement {
+    /// log time as printed by the server (e.g. `2024-01-01 12:00:00.000 UTC`)
+    pub log_time: String,
+    /// user executing the statement
+    pub user: String,
+    /// database the statement was executed on
+    pub database: String,
+    /// process id of the executing backend
+    pub backend_pid: Option<u32>,
+    /// sql of the statement
+    pub statement: String,
+    /// bound parameters of prepared statements (e.g. `$1 = '42'`)
+    pub parameters: Option<String>,
+}
+
+///
+/// Server log entry
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgLogEvent {
+    /// severity
+    pub level: PgLogLevel,
+    /// sqlstate code (e.g. `23505` for unique violations)
+    pub sqlstate: Option<String>,
+    /// primary message
+    pub message: String,
+    /// message detail
+    pub detail: Option<String>,
+    /// process id of the logging backend
+    pub backend_pid: Option<u32>,
+}
+
+///
+/// Statement exceeding the slow query threshold
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgSlowQuery {
+    /// execution time in milliseconds
+    pub duration_ms: f64,
+    /// database the statement was executed on
+    pub database: String,
+    /// sql of the statement
+    pub statement: String,
+    /// bound parameters of prepared statements (e.g. `$1 = '42'`)
+    pub parameters: Option<String>,
+    /// execution plan logged by `auto_explain`
+    pub plan: Option<String>,
+}
+
+///
+/// Parse the slow statements of a csv server log
+///
+/// Returns the statements logged by `log_min_duration_statement` with the plan logged by
+/// `auto_explain` for the same backend, slowest first.
+///
+pub fn parse_slow_queries(csv_log: &str) -> Vec<PgSlowQuery> {
+    let mut plans: HashMap<String, String> = HashMap::new();
+    let mut slow_queries = Vec::new();
+    for record in parse_csv_records(csv_log) {
+        let field = |index: usize| record.get(index).cloned().unwrap_or_default();
+        let message = field(CSV_MESSAGE);
+        let (duration_ms, entry) = match message
+            .strip_prefix("duration: ")
+            .and_then(|message| message.split_once(" ms  "))
+            .and_then(|(duration, entry)| Some((duration.parse::<f64>().ok()?, entry)))
+        {
+            Some(duration_entry) => duration_entry,
+            None => continue,
+        };
+        // auto_explain logs the plan before the statement duration is logged
+        if let Some(plan) = entry.strip_prefix("plan:") {
+            plans.insert(field(CSV_PROCESS_ID), plan.trim().to_string());
+            continue;
+        }
+        let statement = if let Some(statement) = entry.strip_prefix("statement: ") {
+            statement.to_string()
+        } else if let Some(execute) = entry.strip_prefix("execute ") {
+            match execute.split_once(": ") {
+                Some((_, statement)) => statement.to_string(),
+                None => continue,
+            }
+        } else {
+            continue;
+        };
+        slow_queries.push(PgSlowQuery {
+            duration_ms,
+            database: field(CSV_DATABASE_NAME),
+            statement,
+            parameters: field(CSV_DETAIL)
+                .strip_prefix("parameters: ")
+                .map(str::to_string),
+            plan: plans.remove(&field(CSV_PROCESS_ID)),
+        });
+    }
+    slow_queries.sort_by(|a, b| {
+        b.duration_ms
+            .partial_cmp(&a.duration_ms)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    slow_queries
+}
+
+///
+/// Parse the entries of a csv server log
+///
+/// Entries with unknown severities are skipped.
+///
+pub fn parse_log_events(csv_log: &str) -> Vec<PgLogEvent> {
+    parse_csv_records(csv_log)
+        .into_iter()
+        .filter_map(|record| {
+            let field = |index: usize| record.get(index).cloned().unwrap_or_default();
+            let non_empty = |value: String| if value.is_empty() { None } else { Some(value) };
+            Some(PgLogEvent {
+                level: PgLogLevel::parse(&field(CSV_SEVERITY))?,
+                // successful completion is logged for entries without error
+                sqlstate: non_empty(field(CSV_SQLSTATE)).filter(|code| code != "00000"),
+                message: field(CSV_MESSAGE),
+                detail: non_empty(field(CSV_DETAIL)),
+                backend_pid: field(CSV_PROCESS_ID).parse().ok(),
+            })
+        })
+        .collect()
+}
+
+///
+/// Parse the statements of a csv server log
+///
+/// Handles simple (`statement: ...`) and extended protocol (`execute <name>: ...`)
+/// log entries, other entries are skipped.
+///
+pub fn parse_captured_statements(csv_log: &str) -> Vec<PgCapturedStatement> {
+    parse_csv_records(csv_log)
+        .into_iter()
+        .filter_map(|record| {
+            let field = |index: usize| record.get(index).cloned().unwrap_or_default();
+            let message = field(CSV_MESSAGE);
+            let statement = if let Some(statement) = message.strip_prefix("statement: ") {
+                statement.to_string()
+            } else if let Some(execute) = message.strip_prefix("execute ") {
+                let (_, statement) = execute.split_once(": ")?;
+                statement.to_string()
+            } else {
+                return None;
+            };
+            let parameters = field(CSV_DETAIL)
+                .strip_prefix("parameters: ")
+                .map(str::to_string);
+            Some(PgCapturedStatement {
+                log_time: field(CSV_LOG_TIME),
+                user: field(CSV_USER_NAME),
+                database: field(CSV_DATABASE_NAME),
+                backend_pid: field(CSV_PROCESS_ID).parse().ok(),
+                statement,
+                parameters,
+            })
+        })
+        .collect()
+}
+
+///
+/// Current log file of a log destination (`stderr`, `csvlog` or `jsonlog`)
+///
+/// Returns the log file path, `None` if the logging collector doesn't write the destination.
+///
+fn current_log_file(database_dir: &Path, destination: &str) -> Option<PathBuf> {
+    let current_log_files =
+        std::fs::read_to_string(database_dir.join(CURRENT_LOG_FILES_NAME)).ok()?;
+    current_log_files.lines().find_map(|line| {
+        let (line_destination, path) = line.split_once(' ')?;
+        if line_destination == destination {
+            Some(database_dir.join(path))
+        } else {
+            None
+        }
+    })
+}
+
+///
+/// Length of the complete records of csv content
+///
+/// A record is complete once its terminating line break (outside of quotes) was written.
+///
+fn complete_records_len(content: &[u8]) -> usize {
+    let mut quoted = false;
+    let mut len = 0;
+    for (index, c) in content.iter().enumerate() {
+        match c {
+            b'"' => quoted = !quoted,
+            b'\n' if !quoted => len = index + 1,
+            _ => {}
+        }
+    }
+    len
+}
+
+///
+/// Split csv content into records
+///
+/// Quoted fields may contain separators, line breaks and doubled quotes.
+///
+pub(crate) fn parse_csv_records(content: &str) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = content.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => record.push(std::mem::take(&mut field)),
+            '\n' if !quoted => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            '\r' if !quoted => {}
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    records
+}
+
+///
+/// Tails the csv server log
+///
+/// Created by [PgEmbed::log_watcher], requires
+/// [PgSettings::structured_log](crate::postgres::PgSettings::structured_log).
+///
+pub struct PgLogWatcher {
+    /// database directory containing the list of current log files
+    database_dir: PathBuf,
+    /// followed csv log file
+    log_file: Option<PathBuf>,
+    /// read position in the log file
+    offset: u64,
+    /// minimum severity of the returned entries
+    min_level: PgLogLevel,
+    /// delay between polls of the log file
+    poll_interval: Duration,
+}
+
+impl PgLogWatcher {
+    ///
+    /// Only return entries of at least severity `level`
+    ///
+    pub fn min_level(mut self, level: PgLogLevel) -> Self {
+        self.min_level = level;
+        self
+    }
+
+    ///
+    /// Delay between polls of the log file of [PgLogWatcher::into_stream]
+    ///
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    ///
+    /// Entries written since the last call
+    ///
+    /// Incomplete entries are returned once they are completely written. A truncated log file
+    /// is read from the start again, after a rotation the new log file is followed.
+    ///
+    /// Returns the entries on success, otherwise returns an error.
+    ///
+    pub async fn poll(&mut self) -> PgResult<Vec<PgLogEvent>> {
+        let current = current_log_file(&self.database_dir, "csvlog");
+        let mut events = Vec::new();
+        if current != self.log_file {
+            // drain the rotated log file
+            events.extend(self.read_new_events().await?);
+            self.log_file = current;
+            self.offset = 0;
+        }
+        events.extend(self.read_new_events().await?);
+        Ok(events)
+    }
+
+    ///
+    /// Entries appended to the followed log file
+    ///
+    async fn read_new_events(&mut self) -> PgResult<Vec<PgLogEvent>> {
+        let log_file = match &self.log_file {
+            Some(log_file) => log_file,
+            None => return Ok(Vec::new()),
+        };
+        let to_read_error = |e: std::io::Error| PgEmbedError {
+            error_type: PgEmbedErrorType::ReadFileError,
+            source: Some(Box::new(e)),
+            message: Some(format!("could not read server log {}", log_file.display())),
+        };
+        let mut file = match tokio::fs::File::open(log_file).await {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(to_read_error(e)),
+        };
+        let file_len = file.metadata().map_err(to_read_error).await?.len();
+        if file_len < self.offset {
+            self.offset = 0;
+        }
+        let mut new_content = Vec::new();
+        file.seek(SeekFrom::Start(self.offset))
+            .map_err(to_read_error)
+            .await?;
+        file.read_to_end(&mut new_content)
+            .map_err(to_read_error)
+            .await?;
+        let len = complete_records_len(&new_content);
+        let min_level = self.min_level;
+        let events = parse_log_events(&String::from_utf8_lossy(&new_content[..len]))
+            .into_iter()
+            .filter(|event| event.level >= min_level)
+            .collect();
+        self.offset += len as u64;
+        Ok(events)
+    }
+
+    ///
+    /// Stream of the log entries
+    ///
+    /// Polls the log file every [PgLogWatcher::poll_interval], the stream ends after an error.
+    ///
+    pub fn into_stream(self) -> impl Stream<Item = PgResult<PgLogEvent>> {
+        futures::stream::unfold(Some((self, VecDeque::new())), |state| async move {
+            let (mut watcher, mut pending) = state?;
+            loop {
+                if let Some(event) = pending.pop_front() {
+                    return Some((Ok(event), Some((watcher, pending))));
+                }
+                match watcher.poll().await {
+                    Ok(events) if events.is_empty() => {
+                        tokio::time::sleep(watcher.poll_interval).await
+                    }
+                    Ok(events) => pending.extend(events),
+                    Err(e) => return Some((Err(e), None)),
+                }
+            }
+        })
+    }
+}
+
+impl PgEmbed {
+    ///
+    /// Watcher of the server log entries written from now on
+    ///
+    /// Requires [PgSettings::structured_log](crate::postgres::PgSettings::structured_log),
+    /// otherwise no entries are returned.
+    ///
+    pub fn log_watcher(&self) -> PgLogWatcher {
+        let database_dir = self.pg_access.database_dir.clone();
+        let log_file = current_log_file(&database_dir, "csvlog");
+        let offset = log_file
+            .as_ref()
+            .and_then(|log_file| std::fs::metadata(log_file).ok())
+            .map(|metadata| metadata.len())
+            .unwrap_or(0);
+        PgLogWatcher {
+            database_dir,
+            log_file,
+            offset,
+            min_level: PgLogLevel::Debug,
+            poll_interval: Duration::from_millis(100),
+        }
+    }
+
+    ///
+    /// Server configuration of the csv log
+    ///
+    pub(crate) fn structured_log_config() -> BTreeMap<String, String> {
+        let mut config = BTreeMap::new();
+        config.insert("logging_collector".to_string(), "on".to_string());
+        config.insert("log_destination".to_string(), "csvlog".to_string());
+        config.insert(
+            "log_directory".to_string(),
+            PG_EMBED_LOG_DIR_NAME.to_string(),
+        );
+        config.insert(
+            "log_filename".to_string(),
+            PG_EMBED_LOG_FILE_NAME.to_string(),
+        );
+        config
+    }
+
+    ///
+    /// Current server log file
+    ///
+    /// Requires the logging collector ([PgLogConfig::collector](crate::pg_config::PgLogConfig::collector)
+    /// or [PgSettings::structured_log](crate::postgres::PgSettings::structured_log)),
+    /// the plain text log file takes precedence over the csv log file.
+    ///
+    /// Returns the log file path, `None` if the server log isn't written to files.
+    ///
+    pub fn log_path(&self) -> Option<PathBuf> {
+        let database_dir = &self.pg_access.database_dir;
+        current_log_file(database_dir, "stderr")
+            .or_else(|| current_log_file(database_dir, "csvlog"))
+            .or_else(|| current_log_file(database_dir, "jsonlog"))
+    }
+
+    ///
+    /// Last `lines` lines of the current server log file
+    ///
+    /// E.g. to include the server log in the report of a failed test.
+    ///
+    /// Returns the lines on success (none without a log file), otherwise returns an error.
+    ///
+    pub async fn read_log_tail(&self, lines: usize) -> PgResult<Vec<String>> {
+        let log_path = match self.log_path() {
+            Some(log_path) => log_path,
+            None => return Ok(Vec::new()),
+        };
+        let content = tokio::fs::read(&log_path)
+            .map_err(|e| PgEmbedError {
+                error_type: PgEmbedErrorType::ReadFileError,
+                source: Some(Box::new(e)),
+                message: Some(format!("could not read server log {}", log_path.display())),
+            })
+            .await?;
+        let content = String::from_utf8_lossy(&content);
+        let all_lines: Vec<&str> = content.lines().collect();
+        let start = all_lines.len().saturating_sub(lines);
+        Ok(all_lines[start..]
+            .iter()
+            .map(|line| line.to_string())
+            .collect())
+    }
+
+    ///
+    /// csv log files of the statement capture, ordered by name
+    ///
+    async fn csv_log_files(&self) -> PgResult<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        let log_dir = match current_log_file(&self.pg_access.database_dir, "csvlog")
+            .and_then(|log_file| log_file.parent().map(Path::to_path_buf))
+        {
+            Some(log_dir) => log_dir,
+            None => return Ok(files),
+        };
+        let mut entries = match tokio::fs::read_dir(&log_dir).await {
+            Ok(entries) => entries,
+            Err(_) => return Ok(files),
+        };
+        while let Some(entry) = entries
+            .next_entry()
+            .map_err(|e| PgEmbedError {
+                error_type: PgEmbedErrorType::ReadFileError,
+                source: Some(Box::new(e)),
+                message: None,
+            })
+            .await?
+        {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) == Some("csv") {
+                files.push(path);
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    ///
+    /// Statements executed since the start or the last [PgEmbed::clear_captured_statements]
+    ///
+    /// Requires [PgSettings::capture_statements](crate::postgres::PgSettings::capture_statements),
+    /// otherwise no statements are returned.
+    ///
+    /// Returns the statements in execution order on success, otherwise returns an error.
+    ///
+    pub async fn captured_statements(&self) -> PgResult<Vec<PgCapturedStatement>> {
+        let mut statements = Vec::new();
+        for file in self.csv_log_files().await? {
+            let content = tokio::fs::read(&file)
+                .map_err(|e| PgEmbedError {
+                    error_type: PgEmbedErrorType::ReadFileError,
+                    source: Some(Box::new(e)),
+                    message: Some(format!("could not read server log {}", file.display())),
+                })
+                .await?;
+            statements.extend(parse_captured_statements(&String::from_utf8_lossy(
+                &content,
+            )));
+        }
+        Ok(statements)
+    }
+
+    ///
+    /// Statements exceeding the slow query threshold, slowest first
+    ///
+    /// Requires [PgSettings::slow_query_threshold](crate::postgres::PgSettings::slow_query_threshold),
+    /// otherwise no statements are returned. [PgEmbed::clear_captured_statements] discards the
+    /// slow statements as well.
+    ///
+    /// Returns the statements on success, otherwise returns an error.
+    ///
+    pub async fn slow_query_report(&self) -> PgResult<Vec<PgSlowQuery>> {
+        let mut csv_log = String::new();
+        for file in self.csv_log_files().await? {
+            let content = tokio::fs::read(&file)
+                .map_err(|e| PgEmbedError {
+                    error_type: PgEmbedErrorType::ReadFileError,
+                    source: Some(Box::new(e)),
+                    message: Some(format!("could not read server log {}", file.display())),
+                })
+                .await?;
+            csv_log.push_str(&String::from_utf8_lossy(&content));
+        }
+        Ok(parse_slow_queries(&csv_log))
+    }
+
+    ///
+    /// Discard the captured statements
+    ///
+    /// The server appends to the truncated log files, so the statements of e.g. the
+    /// migrations can be excluded from the statements of a test.
+    ///
+    /// Returns `Ok(())` on success, otherwise returns an error.
+    ///
+    pub async fn clear_captured_statements(&self) -> PgResult<()> {
+        for file in self.csv_log_files().await? {
+            tokio::fs::OpenOptions::new()
+                .write(true)
+                .truncate(true)
+                .open(&file)
+                .map_err(|e| PgEmbedError {
+                    error_type: PgEmbedErrorType::WriteFileError,
+                    source: Some(Box::new(e)),
+                    message: Some(format!("could not truncate server log {}", file.display())),
+                })
+                .await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,367 @@
+//!
+//! Server metrics
+//!
+//! Samples `pg_stat_database` and `pg_stat_activity`, e.g. to observe load tests against
+//! the embedded server. The samples are exposed in the Prometheus text format by
+//! [PgEmbed::serve_metrics] or, with the `metrics` feature, recorded with the
+//! [metrics](https://docs.rs/metrics) crate by [PgEmbed::record_metrics].
+//!
+//! With [PgSettings::stat_statements](crate::postgres::PgSettings::stat_statements)
+//! [PgEmbed::top_statements] reports the most expensive statements of `pg_stat_statements`.
+//!
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use futures::TryFutureExt;
+use log::warn;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::task::JoinHandle;
+
+use crate::pg_errors::{PgEmbedError, PgEmbedErrorType};
+use crate::pg_types::PgResult;
+use crate::postgres::PgEmbed;
+
+/// Counters of `pg_stat_database`, in the column order of the metrics query
+const DATABASE_COUNTERS: [&str; 10] = [
+    "xact_commit",
+    "xact_rollback",
+    "blks_read",
+    "blks_hit",
+    "tup_returned",
+    "tup_fetched",
+    "tup_inserted",
+    "tup_updated",
+    "tup_deleted",
+    "deadlocks",
+];
+
+///
+/// Statistics of a database (`pg_stat_database`)
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgDatabaseMetrics {
+    /// database name
+    pub database: String,
+    /// number of connected backends
+    pub backends: u64,
+    /// cumulative counters by column name (e.g. `xact_commit`, `blks_hit`)
+    pub counters: BTreeMap<String, u64>,
+}
+
+///
+/// Sample of the server statistics
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgMetrics {
+    /// statistics of the databases
+    pub databases: Vec<PgDatabaseMetrics>,
+    /// client connections by state (e.g. `active`, `idle`, `idle in transaction`)
+    pub connections: BTreeMap<String, u64>,
+}
+
+impl PgMetrics {
+    ///
+    /// Metrics in the Prometheus text exposition format
+    ///
+    pub fn to_prometheus(&self) -> String {
+        let mut text = String::new();
+        let _ = writeln!(text, "# TYPE pg_stat_database_numbackends gauge");
+        for database in &self.databases {
+            let _ = writeln!(
+                text,
+                "pg_stat_database_numbackends{{datname=\"{}\"}} {}",
+                escape_label(&database.database),
+                database.backends
+            );
+        }
+        for counter in DATABASE_COUNTERS.iter() {
+            let _ = writeln!(text, "# TYPE pg_stat_database_{} counter", counter);
+            for database in &self.databases {
+                if let Some(value) = database.counters.get(*counter) {
+                    let _ = writeln!(
+                        text,
+                        "pg_stat_database_{}{{datname=\"{}\"}} {}",
+                        counter,
+                        escape_label(&database.database),
+                        value
+                    );
+                }
+            }
+        }
+        let _ = writeln!(text, "# TYPE pg_stat_activity_count gauge");
+        for (state, count) in &self.connections {
+            let _ = writeln!(
+                text,
+                "pg_stat_activity_count{{state=\"{}\"}} {}",
+                escape_label(state),
+                count
+            );
+        }
+        text
+    }
+
+    ///
+    /// Record the metrics with the installed [metrics](https://docs.rs/metrics) recorder
+    ///
+    #[cfg(feature = "metrics")]
+    pub fn record(&self) {
+        for database in &self.databases {
+            metrics::gauge!(
+                "pg_stat_database_numbackends",
+                database.backends as f64,
+                "datname" => database.database.clone()
+            );
+            for (counter, value) in &database.counters {
+                metrics::absolute_counter!(
+                    format!("pg_stat_database_{}", counter),
+                    *value,
+                    "datname" => database.database.clone()
+                );
+            }
+        }
+        for (state, count) in &self.connections {
+            metrics::gauge!(
+                "pg_stat_activity_count",
+                *count as f64,
+                "state" => state.clone()
+            );
+        }
+    }
+}
+
+///
+/// Statement statistics (`pg_stat_statements`)
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgStatementStats {
+    /// normalized statement text, whitespace collapsed to single spaces
+    pub query: String,
+    /// number of executions
+    pub calls: u64,
+    /// total execution time in milliseconds
+    pub total_time_ms: f64,
+    /// mean execution time in milliseconds
+    pub mean_time_ms: f64,
+    /// total number of retrieved or affected rows
+    pub rows: u64,
+}
+
+///
+/// Escape a Prometheus label value
+///
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+///
+/// Background metrics task, stopped on drop
+///
+pub struct PgMetricsHandle {
+    /// sampling or serving task
+    task: JoinHandle<()>,
+    /// address of the metrics endpoint
+    local_addr: Option<SocketAddr>,
+}
+
+impl PgMetricsHandle {
+    ///
+    /// Address of the metrics endpoint started by [PgEmbed::serve_metrics]
+    ///
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+}
+
+impl Drop for PgMetricsHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl PgEmbed {
+    ///
+    /// Sample the server statistics
+    ///
+    /// Returns the sample on success, otherwise returns an error.
+    ///
+    pub async fn sample_metrics(&self) -> PgResult<PgMetrics> {
+        let sql = format!(
+            "SELECT datname, numbackends, {} FROM pg_stat_database \
+             WHERE datname IS NOT NULL ORDER BY datname",
+            DATABASE_COUNTERS.join(", ")
+        );
+        let databases = self
+            .psql_rows("postgres", &sql)
+            .await?
+            .into_iter()
+            .map(|row| {
+                let value = |index: usize| {
+                    row.get(index)
+                        .and_then(|value| value.parse::<u64>().ok())
+                        .unwrap_or_default()
+                };
+                PgDatabaseMetrics {
+                    database: row[0].clone(),
+                    backends: value(1),
+                    counters: DATABASE_COUNTERS
+                        .iter()
+                        .enumerate()
+                        .map(|(index, counter)| (counter.to_string(), value(index + 2)))
+                        .collect(),
+                }
+            })
+            .collect();
+        let connections = self
+            .psql_rows(
+                "postgres",
+                "SELECT coalesce(state, 'unknown'), count(*) FROM pg_stat_activity \
+                 WHERE datname IS NOT NULL AND pid <> pg_backend_pid() GROUP BY 1",
+            )
+            .await?
+            .into_iter()
+            .map(|row| {
+                let count = row
+                    .get(1)
+                    .and_then(|count| count.parse::<u64>().ok())
+                    .unwrap_or_default();
+                (row[0].clone(), count)
+            })
+            .collect();
+        Ok(PgMetrics {
+            databases,
+            connections,
+        })
+    }
+
+    ///
+    /// Most expensive statements of a database by total execution time
+    ///
+    /// Creates the `pg_stat_statements` extension in the database if missing, which requires
+    /// [PgSettings::stat_statements](crate::postgres::PgSettings::stat_statements).
+    ///
+    /// Returns up to `n` statements on success, otherwise returns an error.
+    ///
+    pub async fn top_statements(&self, db_name: &str, n: usize) -> PgResult<Vec<PgStatementStats>> {
+        self.create_extension(db_name, "pg_stat_statements").await?;
+        // the timing columns were renamed in postgresql 13
+        let (total_time, mean_time) = if self.fetch_settings.version.major() >= 13 {
+            ("total_exec_time", "mean_exec_time")
+        } else {
+            ("total_time", "mean_time")
+        };
+        let sql = format!(
+            "SELECT regexp_replace(query, '\\s+', ' ', 'g'), calls, {total}, {mean}, rows \
+             FROM pg_stat_statements \
+             WHERE dbid = (SELECT oid FROM pg_database WHERE datname = current_database()) \
+             ORDER BY {total} DESC LIMIT {n}",
+            total = total_time,
+            mean = mean_time,
+            n = n
+        );
+        self.psql_rows(db_name, &sql)
+            .await?
+            .into_iter()
+            .map(|row| {
+                let column = |index: usize| row.get(index).map(String::as_str).unwrap_or_default();
+                let parse_error = || PgEmbedError {
+                    error_type: PgEmbedErrorType::SqlQueryError,
+                    source: None,
+                    message: Some(format!("unexpected pg_stat_statements row {:?}", row)),
+                };
+                Ok(PgStatementStats {
+                    query: column(0).to_string(),
+                    calls: column(1).parse().map_err(|_| parse_error())?,
+                    total_time_ms: column(2).parse().map_err(|_| parse_error())?,
+                    mean_time_ms: column(3).parse().map_err(|_| parse_error())?,
+                    rows: column(4).parse().map_err(|_| parse_error())?,
+                })
+            })
+            .collect()
+    }
+
+    ///
+    /// Serve the server statistics in the Prometheus text format
+    ///
+    /// Every request to the endpoint at `addr` (port 0 selects a free port, see
+    /// [PgMetricsHandle::local_addr]) samples the statistics. The endpoint stops when the
+    /// handle is dropped or the PgEmbed instance is dropped.
+    ///
+    /// Returns the endpoint handle on success, otherwise returns an error.
+    ///
+    pub async fn serve_metrics(self: &Arc<Self>, addr: SocketAddr) -> PgResult<PgMetricsHandle> {
+        let listener = tokio::net::TcpListener::bind(addr)
+            .map_err(|e| PgEmbedError {
+                error_type: PgEmbedErrorType::PgError,
+                source: Some(Box::new(e)),
+                message: Some(format!("could not bind the metrics endpoint {}", addr)),
+            })
+            .await?;
+        let local_addr = listener.local_addr().ok();
+        let pg = Arc::downgrade(self);
+        let task = tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let pg = match pg.upgrade() {
+                    Some(pg) => pg,
+                    None => break,
+                };
+                // the request is not interpreted, any path serves the metrics
+                let mut request = [0u8; 1024];
+                let _ = stream.read(&mut request).await;
+                let response = match pg.sample_metrics().await {
+                    Ok(metrics) => {
+                        let body = metrics.to_prometheus();
+                        format!(
+                            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\n\
+                             Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                            body.len(),
+                            body
+                        )
+                    }
+                    Err(e) => {
+                        warn!("could not sample metrics: {}", e);
+                        "HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\n\
+                         Connection: close\r\n\r\n"
+                            .to_string()
+                    }
+                };
+                let _ = stream.write_all(response.as_bytes()).await;
+                let _ = stream.shutdown().await;
+            }
+        });
+        Ok(PgMetricsHandle { task, local_addr })
+    }
+
+    ///
+    /// Record the server statistics with the [metrics](https://docs.rs/metrics) crate
+    ///
+    /// Samples the statistics every `interval` until the handle or the PgEmbed instance is
+    /// dropped. Failed samples are logged and skipped.
+    ///
+    #[cfg(feature = "metrics")]
+    pub fn record_metrics(self: &Arc<Self>, interval: std::time::Duration) -> PgMetricsHandle {
+        let pg = Arc::downgrade(self);
+        let task = tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let pg = match pg.upgrade() {
+                    Some(pg) => pg,
+                    None => break,
+                };
+                match pg.sample_metrics().await {
+                    Ok(metrics) => metrics.record(),
+                    Err(e) => warn!("could not sample metrics: {}", e),
+                }
+            }
+        });
+        PgMetricsHandle {
+            task,
+            local_addr: None,
+        }
+    }
+}
@@ -0,0 +1,86 @@
+//!
+//! OpenTelemetry spans
+//!
+//! With the `otel` feature, setup, migrations, start and stop are recorded as
+//! OpenTelemetry spans of the globally installed tracer provider
+//! (`opentelemetry::global::set_tracer_provider`). The spans are children of the current
+//! OpenTelemetry context and carry the postgresql version, port and database directory,
+//! so the embedded database overhead shows up in the traces of the tests using it.
+//!
+//! Without the `otel` feature the spans are no-ops.
+//!
+#[cfg(feature = "otel")]
+use opentelemetry::trace::{Span, Status, Tracer};
+#[cfg(feature = "otel")]
+use opentelemetry::KeyValue;
+
+use crate::pg_types::PgResult;
+use crate::postgres::PgEmbed;
+
+/// Instrumentation name of the tracer
+#[cfg(feature = "otel")]
+const PG_EMBED_TRACER_NAME: &str = "pg-embed";
+
+///
+/// Span of a lifecycle operation, ended by [PgLifecycleSpan::end]
+///
+pub(crate) struct PgLifecycleSpan {
+    #[cfg(feature = "otel")]
+    span: opentelemetry::global::BoxedSpan,
+}
+
+impl PgLifecycleSpan {
+    ///
+    /// Add an attribute
+    ///
+    #[cfg_attr(not(feature = "otel"), allow(unused_mut, unused_variables))]
+    pub(crate) fn with_attribute(mut self, key: &'static str, value: String) -> Self {
+        #[cfg(feature = "otel")]
+        self.span.set_attribute(KeyValue::new(key, value));
+        self
+    }
+
+    ///
+    /// End the span, recording a failed operation as error
+    ///
+    #[cfg_attr(not(feature = "otel"), allow(unused_mut, unused_variables))]
+    pub(crate) fn end<T>(mut self, result: &PgResult<T>) {
+        #[cfg(feature = "otel")]
+        {
+            if let Err(e) = result {
+                self.span.set_status(Status::error(e.to_string()));
+            }
+            self.span.end();
+        }
+    }
+}
+
+impl PgEmbed {
+    ///
+    /// Start the span of a lifecycle operation (e.g. `setup`)
+    ///
+    #[cfg_attr(not(feature = "otel"), allow(unused_variables))]
+    pub(crate) fn lifecycle_span(&self, operation: &'static str) -> PgLifecycleSpan {
+        #[cfg(feature = "otel")]
+        {
+            let tracer = opentelemetry::global::tracer(PG_EMBED_TRACER_NAME);
+            let mut span = tracer.start(format!("pg_embed.{}", operation));
+            span.set_attribute(KeyValue::new("db.system", "postgresql"));
+            span.set_attribute(KeyValue::new(
+                "db.version",
+                self.fetch_settings.version.to_string(),
+            ));
+            span.set_attribute(KeyValue::new(
+                "server.port",
+                i64::from(self.pg_settings.port),
+            ));
+            span.set_attribute(KeyValue::new(
+                "pg_embed.database_dir",
+                self.pg_access.database_dir.to_string_lossy().to_string(),
+            ));
+            PgLifecycleSpan { span }
+        }
+        #[cfg(not(feature = "otel"))]
+        PgLifecycleSpan {}
+    }
+}
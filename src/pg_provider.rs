@@ -0,0 +1,282 @@
+//!
+//! Postgresql binary providers
+//!
+//! Abstracts the acquisition of postgresql binaries.
+//! [PgFetchSettings](crate::pg_fetch::PgFetchSettings) is the default provider, downloading the
+//! binaries from a maven repository.
+//!
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use async_trait::async_trait;
+use futures::TryFutureExt;
+use sha2::{Digest, Sha256};
+
+use crate::pg_access::{parse_version_output, PgAccess};
+use crate::pg_errors::{PgEmbedError, PgEmbedErrorType};
+use crate::pg_fetch::PostgresVersion;
+use crate::pg_types::PgResult;
+
+///
+/// Provider of postgresql binaries
+///
+#[async_trait]
+pub trait PgBinaryProvider: Send + Sync {
+    ///
+    /// Key identifying the provided binaries
+    ///
+    /// Used as cache directory path relative to the pg-embed cache directory,
+    /// so different binaries must have different keys.
+    ///
+    fn cache_key(&self) -> String;
+
+    ///
+    /// Directory of already installed binaries (containing `bin/`)
+    ///
+    /// If set, the binaries are used from this directory instead of the cache
+    /// and [PgBinaryProvider::fetch] is never called.
+    ///
+    fn installation_dir(&self) -> Option<PathBuf> {
+        None
+    }
+
+    ///
+    /// Fetch the binaries archive
+    ///
+    /// The archive is written to `archive_path`, any supported
+    /// [ArchiveFormat](crate::pg_unpack::ArchiveFormat) may be used.
+    ///
+    /// Returns `Ok(())` on success, otherwise returns an error.
+    ///
+    async fn fetch(&self, archive_path: &Path) -> PgResult<()>;
+}
+
+///
+/// Provides binaries from an archive on disk
+///
+pub struct LocalArchiveProvider {
+    /// archive file path
+    pub path: PathBuf,
+}
+
+impl LocalArchiveProvider {
+    ///
+    /// Create a new provider for the archive at `path`
+    ///
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        LocalArchiveProvider { path: path.into() }
+    }
+}
+
+#[async_trait]
+impl PgBinaryProvider for LocalArchiveProvider {
+    ///
+    /// `local/{file_name}-{fingerprint}`
+    ///
+    /// The fingerprint covers the canonical path, size and modification time of the archive,
+    /// so archives with the same file name and updated archives get their own cache entry.
+    ///
+    fn cache_key(&self) -> String {
+        let file_name = self
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let path = std::fs::canonicalize(&self.path).unwrap_or_else(|_| self.path.clone());
+        let mut hasher = Sha256::new();
+        hasher.update(path.to_string_lossy().as_bytes());
+        if let Ok(metadata) = std::fs::metadata(&path) {
+            hasher.update(b"\0");
+            hasher.update(metadata.len().to_string().as_bytes());
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+                .unwrap_or_default();
+            hasher.update(b"\0");
+            hasher.update(modified.as_nanos().to_string().as_bytes());
+        }
+        format!(
+            "local/{}-{}",
+            file_name,
+            &hex::encode(hasher.finalize())[..16]
+        )
+    }
+
+    async fn fetch(&self, archive_path: &Path) -> PgResult<()> {
+        tokio::fs::copy(&self.path, archive_path)
+            .map_err(|e| PgEmbedError {
+                error_type: PgEmbedErrorType::ReadFileError,
+                source: Some(Box::new(e)),
+                message: Some(format!("could not read archive {}", self.path.display())),
+            })
+            .await?;
+        Ok(())
+    }
+}
+
+///
+/// Provides binaries of a postgresql installation on the system
+///
+pub struct SystemPostgresProvider {
+    /// installation directory (containing `bin/`)
+    pub installation_dir: PathBuf,
+}
+
+impl SystemPostgresProvider {
+    ///
+    /// Create a new provider for the installation at `installation_dir`
+    ///
+    pub fn new<P: Into<PathBuf>>(installation_dir: P) -> Self {
+        SystemPostgresProvider {
+            installation_dir: installation_dir.into(),
+        }
+    }
+
+    ///
+    /// Discover the postgresql installation on the system
+    ///
+    /// The binaries directory is determined with `pg_config --bindir`, falling back to
+    /// the `PATH` lookup of initdb. The installed major version must match `version`.
+    ///
+    /// Returns the provider on success, otherwise returns an error.
+    ///
+    pub fn discover(version: &PostgresVersion) -> PgResult<Self> {
+        let bin_dir = Self::pg_config_bin_dir()
+            .or_else(Self::path_bin_dir)
+            .ok_or_else(|| PgEmbedError {
+                error_type: PgEmbedErrorType::InvalidPgPackage,
+                source: None,
+                message: Some(String::from("no postgresql installation found")),
+            })?;
+        let installation_dir = bin_dir.parent().map(Path::to_path_buf).unwrap_or(bin_dir);
+        let provider = Self::new(installation_dir);
+        provider.validate_version(version)?;
+        Ok(provider)
+    }
+
+    ///
+    /// Binaries directory reported by `pg_config --bindir`
+    ///
+    fn pg_config_bin_dir() -> Option<PathBuf> {
+        let output = Command::new("pg_config").arg("--bindir").output().ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let bin_dir = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+        if bin_dir.join(PgAccess::executable_name("initdb")).exists() {
+            Some(bin_dir)
+        } else {
+            None
+        }
+    }
+
+    ///
+    /// Directory in `PATH` containing initdb
+    ///
+    fn path_bin_dir() -> Option<PathBuf> {
+        let path = std::env::var_os("PATH")?;
+        std::env::split_paths(&path)
+            .find(|dir| dir.join(PgAccess::executable_name("initdb")).exists())
+    }
+
+    ///
+    /// Installed postgresql version as reported by `initdb --version`
+    ///
+    pub fn installed_version(&self) -> PgResult<String> {
+        let init_db = PgAccess::executable_path(&self.installation_dir, "initdb");
+        let output = Command::new(&init_db)
+            .arg("--version")
+            .output()
+            .map_err(|e| PgEmbedError {
+                error_type: PgEmbedErrorType::PgProcessError,
+                source: Some(Box::new(e)),
+                message: Some(format!("could not execute {}", init_db.display())),
+            })?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        parse_version_output(&stdout).ok_or_else(|| PgEmbedError {
+            error_type: PgEmbedErrorType::InvalidPgPackage,
+            source: None,
+            message: Some(format!(
+                "unexpected initdb version output {}",
+                stdout.trim()
+            )),
+        })
+    }
+
+    ///
+    /// Check that the installed major version matches `version`
+    ///
+    pub fn validate_version(&self, version: &PostgresVersion) -> PgResult<()> {
+        let installed = self.installed_version()?;
+        let installed_major = installed.split('.').next().unwrap_or_default();
+        if installed_major == version.major().to_string() {
+            Ok(())
+        } else {
+            Err(PgEmbedError {
+                error_type: PgEmbedErrorType::InvalidPgPackage,
+                source: None,
+                message: Some(format!(
+                    "installed postgresql version {} in {} doesn't match the required version {}",
+                    installed,
+                    self.installation_dir.display(),
+                    version
+                )),
+            })
+        }
+    }
+}
+
+/// The postgresql binaries archive embedded at build time
+#[cfg(feature = "bundled")]
+static BUNDLED_ARCHIVE: &[u8] = include_bytes!(env!("PG_EMBED_BUNDLED_ARCHIVE_PATH"));
+
+///
+/// Provides the binaries archive embedded at build time
+///
+/// Requires the `bundled` feature, the archive is read from the path in the
+/// `PG_EMBED_BUNDLED_ARCHIVE` environment variable while building.
+///
+#[cfg(feature = "bundled")]
+pub struct BundledProvider;
+
+#[cfg(feature = "bundled")]
+#[async_trait]
+impl PgBinaryProvider for BundledProvider {
+    fn cache_key(&self) -> String {
+        format!("bundled/{}", env!("PG_EMBED_BUNDLED_ARCHIVE_NAME"))
+    }
+
+    async fn fetch(&self, archive_path: &Path) -> PgResult<()> {
+        tokio::fs::write(archive_path, BUNDLED_ARCHIVE)
+            .map_err(|e| PgEmbedError {
+                error_type: PgEmbedErrorType::WriteFileError,
+                source: Some(Box::new(e)),
+                message: None,
+            })
+            .await
+    }
+}
+
+#[async_trait]
+impl PgBinaryProvider for SystemPostgresProvider {
+    fn cache_key(&self) -> String {
+        let path = self.installation_dir.to_string_lossy();
+        format!("system/{}", path.replace(&['/', '\\', ':'][..], "_"))
+    }
+
+    fn installation_dir(&self) -> Option<PathBuf> {
+        Some(self.installation_dir.clone())
+    }
+
+    async fn fetch(&self, _archive_path: &Path) -> PgResult<()> {
+        Err(PgEmbedError {
+            error_type: PgEmbedErrorType::InvalidPgPackage,
+            source: None,
+            message: Some(format!(
+                "no postgresql binaries found in {}",
+                self.installation_dir.display()
+            )),
+        })
+    }
+}
@@ -0,0 +1,198 @@
+//!
+//! Generated seed data
+//!
+//! Populate tables from a declarative spec, loaded with COPY.
+//!
+use std::io::Write;
+use std::time::Duration;
+
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::pg_errors::{PgEmbedError, PgEmbedErrorType};
+use crate::pg_types::PgResult;
+use crate::postgres::{PgCsvOptions, PgEmbed};
+
+///
+/// Column value generator
+///
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PgSeedGenerator {
+    /// sequential integers starting at the given value
+    Sequence(i64),
+    /// random integers in the inclusive range
+    RandomInt {
+        /// minimum value
+        min: i64,
+        /// maximum value
+        max: i64,
+    },
+    /// random version 4 uuids
+    Uuid,
+    /// random alphanumeric strings of the given length
+    RandomString(usize),
+    /// timestamps (utc) starting at `start` seconds since the unix epoch, advanced by `step`
+    Timestamp {
+        /// first timestamp in seconds since the unix epoch
+        start: u64,
+        /// distance between consecutive rows
+        #[serde(with = "humantime_serde")]
+        step: Duration,
+    },
+    /// the same value for every row
+    Constant(String),
+}
+
+impl PgSeedGenerator {
+    ///
+    /// Generate the csv value of row `row`
+    ///
+    fn generate<R: Rng>(&self, rng: &mut R, row: u64) -> String {
+        match self {
+            PgSeedGenerator::Sequence(start) => (start + row as i64).to_string(),
+            PgSeedGenerator::RandomInt { min, max } => rng.gen_range(*min..=*max).to_string(),
+            PgSeedGenerator::Uuid => {
+                let mut bytes: [u8; 16] = rng.gen();
+                bytes[6] = (bytes[6] & 0x0f) | 0x40;
+                bytes[8] = (bytes[8] & 0x3f) | 0x80;
+                let hex = hex::encode(bytes);
+                format!(
+                    "{}-{}-{}-{}-{}",
+                    &hex[0..8],
+                    &hex[8..12],
+                    &hex[12..16],
+                    &hex[16..20],
+                    &hex[20..32]
+                )
+            }
+            PgSeedGenerator::RandomString(len) => rng
+                .sample_iter(&Alphanumeric)
+                .take(*len)
+                .map(char::from)
+                .collect(),
+            PgSeedGenerator::Timestamp { start, step } => {
+                let offset = step.as_secs_f64() * row as f64;
+                format_timestamp(*start as f64 + offset)
+            }
+            PgSeedGenerator::Constant(value) => {
+                format!("\"{}\"", value.replace('"', "\"\""))
+            }
+        }
+    }
+}
+
+///
+/// Generated table column
+///
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PgSeedColumn {
+    /// column name
+    pub name: String,
+    /// value generator
+    pub generator: PgSeedGenerator,
+}
+
+///
+/// Declarative seed data of a table
+///
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PgSeedSpec {
+    /// table name, may be schema qualified
+    pub table: String,
+    /// number of generated rows
+    pub rows: u64,
+    /// generated columns, other columns get their default values
+    pub columns: Vec<PgSeedColumn>,
+}
+
+impl PgSeedSpec {
+    ///
+    /// Write the generated rows as csv without header
+    ///
+    pub fn write_csv<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let mut rng = rand::thread_rng();
+        for row in 0..self.rows {
+            let values: Vec<String> = self
+                .columns
+                .iter()
+                .map(|column| column.generator.generate(&mut rng, row))
+                .collect();
+            writeln!(writer, "{}", values.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+impl PgEmbed {
+    ///
+    /// Populate a table with generated rows
+    ///
+    /// The rows are written to a temporary csv file and loaded with
+    /// [PgEmbed::copy_from_csv].
+    ///
+    /// Returns the number of loaded rows on success, otherwise returns an error.
+    ///
+    pub async fn seed(&self, db_name: &str, spec: &PgSeedSpec) -> PgResult<u64> {
+        let to_write_error = |e: std::io::Error| PgEmbedError {
+            error_type: PgEmbedErrorType::WriteFileError,
+            source: Some(Box::new(e)),
+            message: Some(format!("could not write the seed data of {}", spec.table)),
+        };
+        let csv_file = tempfile::Builder::new()
+            .prefix("pg-embed-seed-")
+            .suffix(".csv")
+            .tempfile()
+            .map_err(to_write_error)?;
+        let generated_spec = spec.clone();
+        let csv_path = csv_file.path().to_path_buf();
+        tokio::task::spawn_blocking(move || {
+            let mut writer = std::io::BufWriter::new(std::fs::File::create(&csv_path)?);
+            generated_spec.write_csv(&mut writer)?;
+            writer.flush()
+        })
+        .await
+        .map_err(|e| PgEmbedError {
+            error_type: PgEmbedErrorType::PgTaskJoinError,
+            source: Some(Box::new(e)),
+            message: None,
+        })?
+        .map_err(to_write_error)?;
+        let options = PgCsvOptions {
+            header: false,
+            columns: Some(spec.columns.iter().map(|c| c.name.clone()).collect()),
+            ..Default::default()
+        };
+        self.copy_from_csv(db_name, &spec.table, csv_file.path(), &options)
+            .await
+    }
+}
+
+///
+/// Format seconds since the unix epoch as utc timestamp, e.g. `2024-01-31 12:00:00+00`
+///
+fn format_timestamp(epoch_secs: f64) -> String {
+    let secs = epoch_secs.floor() as i64;
+    let days = secs.div_euclid(86_400);
+    let secs_of_day = secs.rem_euclid(86_400);
+    // civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}+00",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60
+    )
+}
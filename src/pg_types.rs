@@ -1,5 +1,10 @@
+use crate::pg_enums::PgEvent;
 use crate::pg_errors::PgEmbedError;
-use std::cell::Cell;
+use crate::pg_fetch::DownloadProgress;
+use futures::future::BoxFuture;
+use std::sync::Arc;
 
 pub type PgResult<T> = Result<T, PgEmbedError>;
-pub type PgCommandSync = Box<Cell<std::process::Command>>;
+pub type PgProgressCallback = Arc<dyn Fn(DownloadProgress) + Send + Sync>;
+pub type PgEventCallback = Arc<dyn Fn(&PgEvent) + Send + Sync>;
+pub type PgMigrateHook = Arc<dyn Fn(String) -> BoxFuture<'static, PgResult<()>> + Send + Sync>;
@@ -1,120 +1,488 @@
 //!
 //! Unpack postgresql binaries
 //!
-use std::path::PathBuf;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
 
-use archiver_rs::{Archive, Compressed};
+use flate2::read::GzDecoder;
 use futures::TryFutureExt;
+use xz2::read::XzDecoder;
 
 use crate::pg_errors::{PgEmbedError, PgEmbedErrorType};
 use crate::pg_types::PgResult;
 
+/// File name suffixes of archives nested in zip archives
+const NESTED_ARCHIVE_SUFFIXES: [&str; 6] =
+    [".txz", ".tar.xz", ".tgz", ".tar.gz", ".tzst", ".tar.zst"];
+/// Maximum number of unpacked archive entries
+const MAX_ENTRIES: usize = 100_000;
+/// Maximum total size of unpacked archive entries (4 GiB)
+const MAX_UNPACKED_SIZE: u64 = 4 * 1024 * 1024 * 1024;
+
 ///
-/// Unzip the postgresql txz file
-///
-/// Returns `Ok(PathBuf(txz_file_path))` file path of the txz archive on success, otherwise returns an error.
+/// Archive formats of postgresql binaries
 ///
-fn unzip_txz(zip_file_path: &PathBuf, cache_dir: &PathBuf) -> Result<PathBuf, PgEmbedError> {
-    let mut zip = archiver_rs::Zip::open(zip_file_path.as_path()).map_err(|e| PgEmbedError {
-        error_type: PgEmbedErrorType::ReadFileError,
-        source: Some(Box::new(e)),
-        message: None,
-    })?;
-    let file_name = zip
-        .files()
-        .map_err(|e| PgEmbedError {
-            error_type: PgEmbedErrorType::UnpackFailure,
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArchiveFormat {
+    /// zip archive, may contain a nested tar archive (e.g. the zonky jar files)
+    Zip,
+    /// gzip compressed tar archive
+    TarGz,
+    /// xz compressed tar archive
+    TarXz,
+    /// zstandard compressed tar archive
+    TarZst,
+}
+
+impl ArchiveFormat {
+    ///
+    /// Detect the archive format by the magic bytes of the archive header
+    ///
+    pub fn detect(header: &[u8]) -> Option<Self> {
+        if header.starts_with(&[0x50, 0x4b, 0x03, 0x04]) {
+            Some(ArchiveFormat::Zip)
+        } else if header.starts_with(&[0x1f, 0x8b]) {
+            Some(ArchiveFormat::TarGz)
+        } else if header.starts_with(&[0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00]) {
+            Some(ArchiveFormat::TarXz)
+        } else if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(ArchiveFormat::TarZst)
+        } else {
+            None
+        }
+    }
+
+    ///
+    /// Detect the archive format of a file
+    ///
+    /// Returns the archive format on success, otherwise returns an error.
+    ///
+    pub fn detect_file(file_path: &Path) -> PgResult<Self> {
+        let mut header = [0u8; 6];
+        let mut file = open_file(file_path)?;
+        let len = file.read(&mut header).map_err(|e| PgEmbedError {
+            error_type: PgEmbedErrorType::ReadFileError,
             source: Some(Box::new(e)),
             message: None,
-        })?
-        .into_iter()
-        .find(|name| name.ends_with(".txz"));
-    match file_name {
-        Some(file_name) => {
-            // decompress zip
-            let mut target_path = cache_dir.clone();
-            target_path.push(&file_name);
-            zip.extract_single(&target_path.as_path(), file_name.clone())
-                .map_err(|e| PgEmbedError {
-                    error_type: PgEmbedErrorType::UnpackFailure,
-                    source: Some(Box::new(e)),
-                    message: None,
-                })?;
-            Ok(target_path)
-        }
-        None => Err(PgEmbedError {
+        })?;
+        Self::detect(&header[..len]).ok_or_else(|| PgEmbedError {
             error_type: PgEmbedErrorType::InvalidPgPackage,
             source: None,
-            message: Some(String::from("no postgresql txz in zip")),
-        }),
+            message: Some(format!("unknown archive format {}", file_path.display())),
+        })
+    }
+}
+
+///
+/// Unpack limits shared by all (nested) archives of an unpack run
+///
+struct UnpackBudget {
+    /// number of unpacked entries
+    entries: usize,
+    /// total size of unpacked entries
+    size: u64,
+}
+
+impl UnpackBudget {
+    fn new() -> Self {
+        UnpackBudget {
+            entries: 0,
+            size: 0,
+        }
+    }
+
+    ///
+    /// Count an entry, fails if [MAX_ENTRIES] is exceeded
+    ///
+    fn add_entry(&mut self) -> PgResult<()> {
+        self.entries += 1;
+        if self.entries > MAX_ENTRIES {
+            return Err(unsafe_archive(format!(
+                "archive contains more than {} entries",
+                MAX_ENTRIES
+            )));
+        }
+        Ok(())
+    }
+
+    ///
+    /// Count unpacked bytes, fails if [MAX_UNPACKED_SIZE] is exceeded
+    ///
+    fn add_size(&mut self, size: u64) -> PgResult<()> {
+        self.size = self.size.saturating_add(size);
+        if self.size > MAX_UNPACKED_SIZE {
+            return Err(unsafe_archive(format!(
+                "archive unpacks to more than {} bytes",
+                MAX_UNPACKED_SIZE
+            )));
+        }
+        Ok(())
+    }
+
+    ///
+    /// Bytes left until [MAX_UNPACKED_SIZE] is reached
+    ///
+    fn remaining_size(&self) -> u64 {
+        MAX_UNPACKED_SIZE.saturating_sub(self.size)
+    }
+}
+
+///
+/// Unsafe archive error
+///
+fn unsafe_archive(message: String) -> PgEmbedError {
+    PgEmbedError {
+        error_type: PgEmbedErrorType::UnsafeArchive,
+        source: None,
+        message: Some(message),
+    }
+}
+
+///
+/// Check that an entry path stays inside the target directory
+///
+/// Rejects absolute paths and `..` components.
+///
+fn check_entry_path(path: &Path) -> PgResult<()> {
+    for component in path.components() {
+        match component {
+            Component::Normal(_) | Component::CurDir => {}
+            _ => {
+                return Err(unsafe_archive(format!(
+                    "archive entry {} escapes the target directory",
+                    path.display()
+                )))
+            }
+        }
     }
+    Ok(())
 }
 
 ///
-/// Decompress the postgresql txz file
+/// Check that a symbolic link target stays inside the target directory
 ///
-/// Returns `Ok(PathBuf(tar_file_path))` (*the file path to the postgresql tar file*) on success, otherwise returns an error.
+/// The link target is resolved relative to the directory of the link entry.
 ///
-fn decompress_xz(file_path: &PathBuf) -> Result<PathBuf, PgEmbedError> {
-    let mut xz = archiver_rs::Xz::open(file_path.as_path()).map_err(|e| PgEmbedError {
+fn check_link_target(path: &Path, target: &Path) -> PgResult<()> {
+    let mut depth = path
+        .parent()
+        .map(|parent| {
+            parent
+                .components()
+                .filter(|component| matches!(component, Component::Normal(_)))
+                .count()
+        })
+        .unwrap_or(0);
+    for component in target.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir if depth > 0 => depth -= 1,
+            _ => {
+                return Err(unsafe_archive(format!(
+                    "archive link {} -> {} escapes the target directory",
+                    path.display(),
+                    target.display()
+                )))
+            }
+        }
+    }
+    Ok(())
+}
+
+///
+/// Open a file for reading
+///
+fn open_file(file_path: &Path) -> PgResult<File> {
+    File::open(file_path).map_err(|e| PgEmbedError {
         error_type: PgEmbedErrorType::ReadFileError,
         source: Some(Box::new(e)),
         message: None,
-    })?;
-    // rename file path suffix from .txz to .tar
-    let target_path = file_path.with_extension(".tar");
-    xz.decompress(&target_path.as_path())
-        .map_err(|e| PgEmbedError {
-            error_type: PgEmbedErrorType::UnpackFailure,
-            source: Some(Box::new(e)),
-            message: None,
+    })
+}
+
+///
+/// Copy at most the remaining budget from `reader` to `writer`
+///
+fn copy_limited<R: Read, W: std::io::Write>(
+    reader: &mut R,
+    writer: &mut W,
+    budget: &mut UnpackBudget,
+) -> PgResult<()> {
+    let copied =
+        std::io::copy(&mut reader.take(budget.remaining_size() + 1), writer).map_err(|e| {
+            PgEmbedError {
+                error_type: PgEmbedErrorType::UnpackFailure,
+                source: Some(Box::new(e)),
+                message: None,
+            }
         })?;
-    Ok(target_path)
+    budget.add_size(copied)
 }
 
 ///
-/// Unpack the postgresql tar file
+/// Unpack a tar archive stream
 ///
 /// Returns `Ok(())` on success, otherwise returns an error.
 ///
-fn decompress_tar(file_path: &PathBuf, cache_dir: &PathBuf) -> Result<(), PgEmbedError> {
-    let mut tar = archiver_rs::Tar::open(&file_path.as_path()).map_err(|e| PgEmbedError {
-        error_type: PgEmbedErrorType::ReadFileError,
+fn unpack_tar<R: Read>(reader: R, target_dir: &Path, budget: &mut UnpackBudget) -> PgResult<()> {
+    let to_unpack_error = |e: std::io::Error| PgEmbedError {
+        error_type: PgEmbedErrorType::UnpackFailure,
         source: Some(Box::new(e)),
         message: None,
-    })?;
+    };
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries().map_err(to_unpack_error)? {
+        let mut entry = entry.map_err(to_unpack_error)?;
+        budget.add_entry()?;
+        budget.add_size(entry.size())?;
+        let path = entry.path().map_err(to_unpack_error)?.into_owned();
+        check_entry_path(&path)?;
+        if let Some(link) = entry.link_name().map_err(to_unpack_error)? {
+            if entry.header().entry_type().is_hard_link() {
+                // hard link targets are relative to the archive root
+                check_entry_path(&link)?;
+            } else {
+                check_link_target(&path, &link)?;
+            }
+        }
+        entry.unpack_in(target_dir).map_err(to_unpack_error)?;
+    }
+    Ok(())
+}
 
-    tar.extract(cache_dir.as_path()).map_err(|e| PgEmbedError {
+///
+/// Unpack a zip archive
+///
+/// If the zip archive contains a nested tar archive, only the nested archive is unpacked.
+///
+/// Returns `Ok(())` on success, otherwise returns an error.
+///
+fn unpack_zip(file_path: &Path, target_dir: &Path, budget: &mut UnpackBudget) -> PgResult<()> {
+    let mut zip = zip::ZipArchive::new(open_file(file_path)?).map_err(|e| PgEmbedError {
         error_type: PgEmbedErrorType::UnpackFailure,
         source: Some(Box::new(e)),
         message: None,
     })?;
+    let nested_archive = zip.file_names().find(|name| {
+        NESTED_ARCHIVE_SUFFIXES
+            .iter()
+            .any(|suffix| name.ends_with(suffix))
+    });
+    match nested_archive.map(|name| name.to_string()) {
+        Some(name) => {
+            // extract the nested archive next to the zip archive and unpack it
+            let nested_path = file_path.with_extension("nested");
+            {
+                let mut entry = zip.by_name(&name).map_err(|e| PgEmbedError {
+                    error_type: PgEmbedErrorType::UnpackFailure,
+                    source: Some(Box::new(e)),
+                    message: None,
+                })?;
+                let mut nested_file = File::create(&nested_path).map_err(|e| PgEmbedError {
+                    error_type: PgEmbedErrorType::WriteFileError,
+                    source: Some(Box::new(e)),
+                    message: None,
+                })?;
+                copy_limited(&mut entry, &mut nested_file, budget)?;
+            }
+            let result = unpack_file(&nested_path, target_dir, budget);
+            std::fs::remove_file(&nested_path).map_err(|e| PgEmbedError {
+                error_type: PgEmbedErrorType::PgCleanUpFailure,
+                source: Some(Box::new(e)),
+                message: None,
+            })?;
+            result
+        }
+        None => {
+            for i in 0..zip.len() {
+                let mut entry = zip.by_index(i).map_err(|e| PgEmbedError {
+                    error_type: PgEmbedErrorType::UnpackFailure,
+                    source: Some(Box::new(e)),
+                    message: None,
+                })?;
+                budget.add_entry()?;
+                let path = PathBuf::from(entry.name());
+                check_entry_path(&path)?;
+                unpack_zip_entry(&mut entry, &target_dir.join(path), budget)?;
+            }
+            Ok(())
+        }
+    }
+}
 
+///
+/// Unpack a single zip archive entry to `target_path`
+///
+fn unpack_zip_entry(
+    entry: &mut zip::read::ZipFile,
+    target_path: &Path,
+    budget: &mut UnpackBudget,
+) -> PgResult<()> {
+    let dir = if entry.is_dir() {
+        Some(target_path)
+    } else {
+        target_path.parent()
+    };
+    if let Some(dir) = dir {
+        std::fs::create_dir_all(dir).map_err(|e| PgEmbedError {
+            error_type: PgEmbedErrorType::DirCreationError,
+            source: Some(Box::new(e)),
+            message: None,
+        })?;
+    }
+    if entry.is_dir() {
+        return Ok(());
+    }
+    let mut file = File::create(target_path).map_err(|e| PgEmbedError {
+        error_type: PgEmbedErrorType::WriteFileError,
+        source: Some(Box::new(e)),
+        message: None,
+    })?;
+    copy_limited(entry, &mut file, budget)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if let Some(mode) = entry.unix_mode() {
+            let _ = std::fs::set_permissions(target_path, std::fs::Permissions::from_mode(mode));
+        }
+    }
     Ok(())
 }
 
+///
+/// Unpack an archive file, counting the unpacked entries against `budget`
+///
+fn unpack_file(file_path: &Path, target_dir: &Path, budget: &mut UnpackBudget) -> PgResult<()> {
+    match ArchiveFormat::detect_file(file_path)? {
+        ArchiveFormat::Zip => unpack_zip(file_path, target_dir, budget),
+        ArchiveFormat::TarGz => {
+            unpack_tar(GzDecoder::new(open_file(file_path)?), target_dir, budget)
+        }
+        ArchiveFormat::TarXz => {
+            unpack_tar(XzDecoder::new(open_file(file_path)?), target_dir, budget)
+        }
+        ArchiveFormat::TarZst => {
+            let decoder = zstd::Decoder::new(open_file(file_path)?).map_err(|e| PgEmbedError {
+                error_type: PgEmbedErrorType::UnpackFailure,
+                source: Some(Box::new(e)),
+                message: None,
+            })?;
+            unpack_tar(decoder, target_dir, budget)
+        }
+    }
+}
+
+///
+/// Unpack an archive of any supported [ArchiveFormat]
+///
+/// Entries escaping `target_dir` (absolute paths, `..` components or links pointing outside)
+/// and archives exceeding the entry count or unpacked size limits are rejected with
+/// [PgEmbedErrorType::UnsafeArchive].
+///
+/// Returns `Ok(())` on success, otherwise returns an error.
+///
+pub fn unpack_archive(file_path: &Path, target_dir: &Path) -> PgResult<()> {
+    // tar entries are unpacked relative to the canonicalized target directory
+    std::fs::create_dir_all(target_dir).map_err(|e| PgEmbedError {
+        error_type: PgEmbedErrorType::DirCreationError,
+        source: Some(Box::new(e)),
+        message: None,
+    })?;
+    unpack_file(file_path, target_dir, &mut UnpackBudget::new())
+}
+
 ///
 /// Unpack the postgresql executables
 ///
+/// The archive format is detected automatically, see [ArchiveFormat].
+/// The archive is unpacked on a blocking thread to not block the async runtime.
+///
 /// Returns `Ok(())` on success, otherwise returns an error.
 ///
+#[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(archive = %zip_file_path.display())))]
 pub async fn unpack_postgres(zip_file_path: &PathBuf, cache_dir: &PathBuf) -> PgResult<()> {
-    let txz_file_path = unzip_txz(&zip_file_path, &cache_dir)?;
-    let tar_file_path = decompress_xz(&txz_file_path)?;
-    tokio::fs::remove_file(txz_file_path)
+    let zip_file_path = zip_file_path.clone();
+    let cache_dir = cache_dir.clone();
+    tokio::task::spawn_blocking(move || unpack_archive(&zip_file_path, &cache_dir))
         .map_err(|e| PgEmbedError {
-            error_type: PgEmbedErrorType::PgCleanUpFailure,
+            error_type: PgEmbedErrorType::UnpackFailure,
             source: Some(Box::new(e)),
             message: None,
         })
-        .await?;
-    let _ = decompress_tar(&tar_file_path, &cache_dir);
-    tokio::fs::remove_file(tar_file_path)
-        .map_err(|e| PgEmbedError {
-            error_type: PgEmbedErrorType::PgCleanUpFailure,
+        .await?
+}
+
+///
+/// Make the unpacked executables runnable
+///
+/// Sets the execute bits of the files in `bin/` and removes the `com.apple.quarantine`
+/// attribute on macOS, so Gatekeeper doesn't block the binaries.
+/// Failures are reported as [PgEmbedErrorType::ExecutableFixupFailure] with remediation info.
+///
+/// Returns `Ok(())` on success, otherwise returns an error.
+///
+pub async fn fixup_executables(installation_dir: &Path) -> PgResult<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let bin_dir = installation_dir.join("bin");
+        let fixup_error = |e: std::io::Error| PgEmbedError {
+            error_type: PgEmbedErrorType::ExecutableFixupFailure,
             source: Some(Box::new(e)),
-            message: None,
-        })
-        .await?;
+            message: Some(format!(
+                "could not set the execute bits, run `chmod +x {}/*`",
+                bin_dir.display()
+            )),
+        };
+        let mut entries = tokio::fs::read_dir(&bin_dir).map_err(fixup_error).await?;
+        while let Some(entry) = entries.next_entry().map_err(fixup_error).await? {
+            let metadata = entry.metadata().map_err(fixup_error).await?;
+            if !metadata.is_file() {
+                continue;
+            }
+            let mut permissions = metadata.permissions();
+            permissions.set_mode(permissions.mode() | 0o111);
+            tokio::fs::set_permissions(entry.path(), permissions)
+                .map_err(fixup_error)
+                .await?;
+        }
+    }
+    #[cfg(target_os = "macos")]
+    {
+        let remediation = format!(
+            "run `xattr -dr com.apple.quarantine {}`",
+            installation_dir.display()
+        );
+        let output = tokio::process::Command::new("xattr")
+            .arg("-dr")
+            .arg("com.apple.quarantine")
+            .arg(installation_dir)
+            .output()
+            .map_err(|e| PgEmbedError {
+                error_type: PgEmbedErrorType::ExecutableFixupFailure,
+                source: Some(Box::new(e)),
+                message: Some(remediation.clone()),
+            })
+            .await?;
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        // binaries without the attribute are reported as error by some xattr versions
+        if !output.status.success() && !stderr.contains("No such xattr") {
+            return Err(PgEmbedError {
+                error_type: PgEmbedErrorType::ExecutableFixupFailure,
+                source: None,
+                message: Some(format!(
+                    "could not remove the quarantine attribute: {}, {}",
+                    stderr.trim(),
+                    remediation
+                )),
+            });
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = installation_dir;
+    }
     Ok(())
 }
@@ -0,0 +1,281 @@
+//!
+//! Support of the `#[pg_embed::test]` attribute
+//!
+//! The tests of a test binary share the [PgEmbed::global] server. Each test gets a fresh
+//! database copied from a template database, which is migrated with the `migrations` folder
+//! of the tested crate (if present). The server is stopped after the last running test
+//! finished.
+//!
+//! Tests running their own servers allocate non-conflicting ports and database directories
+//! with [allocate].
+//!
+use std::collections::HashSet;
+use std::fs::File;
+use std::future::Future;
+use std::ops::Deref;
+use std::panic::AssertUnwindSafe;
+use std::path::PathBuf;
+use std::sync::{Arc, Weak};
+
+use async_trait::async_trait;
+use fs2::FileExt;
+use futures::FutureExt;
+use log::warn;
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+
+use crate::pg_errors::{PgEmbedError, PgEmbedErrorType};
+use crate::pg_factory::{DatabaseFactory, PgDatabaseGuard};
+use crate::pg_types::PgResult;
+use crate::postgres::PgEmbed;
+
+pub use tokio;
+
+/// Name of the template database of the test databases
+const TEST_TEMPLATE_DB: &str = "pg_embed_test_template";
+/// First port handed out by [allocate]
+const ALLOCATED_PORT_START: u16 = 20000;
+/// Number of ports handed out by [allocate]
+const ALLOCATED_PORT_COUNT: u16 = 10000;
+/// Directory of the port reservation lock files, relative to the temporary directory
+const PORT_LOCK_DIR_NAME: &str = "pg-embed-ports";
+/// Directory of the allocated database directories, relative to the temporary directory
+const TEST_DIR_NAME: &str = "pg-embed-tests";
+
+lazy_static! {
+    static ref TEST_FACTORY: Mutex<Weak<DatabaseFactory>> = Mutex::new(Weak::new());
+    /// Ports reserved by this process
+    static ref RESERVED_PORTS: std::sync::Mutex<HashSet<u16>> =
+        std::sync::Mutex::new(HashSet::new());
+}
+
+///
+/// Port and database directory allocated by [allocate]
+///
+/// The port stays reserved for other allocations (also of other processes) until the
+/// allocation is dropped.
+///
+#[derive(Debug)]
+pub struct PgTestAllocation {
+    /// allocated port
+    pub port: u16,
+    /// allocated (empty) database directory
+    pub database_dir: PathBuf,
+    /// locked port reservation file
+    _lock_file: File,
+}
+
+impl Drop for PgTestAllocation {
+    fn drop(&mut self) {
+        if let Ok(mut reserved) = RESERVED_PORTS.lock() {
+            reserved.remove(&self.port);
+        }
+    }
+}
+
+///
+/// Allocate a port and database directory for the test `key`
+///
+/// The port search starts at a port derived from `key`, so a test gets the same port
+/// across runs unless it is in use. Ports reserved by other allocations, of this or other
+/// processes, and ports in use are skipped. The database directory is derived from `key`
+/// and the port, leftovers of earlier runs are removed.
+///
+/// Returns the allocation on success, otherwise returns an error.
+///
+pub fn allocate(key: &str) -> PgResult<PgTestAllocation> {
+    let to_allocation_error = |e: std::io::Error| PgEmbedError {
+        error_type: PgEmbedErrorType::DirCreationError,
+        source: Some(Box::new(e)),
+        message: Some(format!("could not allocate a test directory for {}", key)),
+    };
+    let lock_dir = std::env::temp_dir().join(PORT_LOCK_DIR_NAME);
+    std::fs::create_dir_all(&lock_dir).map_err(to_allocation_error)?;
+    let digest = Sha256::digest(key.as_bytes());
+    let offset = u16::from_be_bytes([digest[0], digest[1]]) % ALLOCATED_PORT_COUNT;
+    for i in 0..ALLOCATED_PORT_COUNT {
+        let port = ALLOCATED_PORT_START + (offset + i) % ALLOCATED_PORT_COUNT;
+        {
+            let mut reserved = RESERVED_PORTS.lock().unwrap_or_else(|e| e.into_inner());
+            if !reserved.insert(port) {
+                continue;
+            }
+        }
+        let lock_file = File::create(lock_dir.join(format!("{}.lock", port)))
+            .ok()
+            .filter(|lock_file| lock_file.try_lock_exclusive().is_ok());
+        let lock_file = match lock_file {
+            Some(lock_file) if port_available(port) => lock_file,
+            _ => {
+                RESERVED_PORTS
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .remove(&port);
+                continue;
+            }
+        };
+        let allocation = PgTestAllocation {
+            port,
+            database_dir: std::env::temp_dir().join(TEST_DIR_NAME).join(format!(
+                "{}-{}",
+                sanitize_key(key),
+                port
+            )),
+            _lock_file: lock_file,
+        };
+        // the port lock guards the directory, so leftovers can be removed safely
+        if allocation.database_dir.exists() {
+            std::fs::remove_dir_all(&allocation.database_dir).map_err(to_allocation_error)?;
+        }
+        std::fs::create_dir_all(&allocation.database_dir).map_err(to_allocation_error)?;
+        return Ok(allocation);
+    }
+    Err(PgEmbedError {
+        error_type: PgEmbedErrorType::PgStartFailure,
+        source: None,
+        message: Some(format!("no free port left to allocate for {}", key)),
+    })
+}
+
+///
+/// Key of the current test
+///
+/// The test harness names the test threads after the running test.
+///
+pub fn current_test_key() -> String {
+    let thread = std::thread::current();
+    match thread.name() {
+        Some(name) if name != "main" => name.to_string(),
+        _ => format!("{:?}", thread.id()),
+    }
+}
+
+///
+/// Check if a local port can be bound
+///
+fn port_available(port: u16) -> bool {
+    std::net::TcpListener::bind(("127.0.0.1", port)).is_ok()
+}
+
+///
+/// File name safe version of a test key
+///
+fn sanitize_key(key: &str) -> String {
+    key.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+///
+/// Parameter of a `#[pg_embed::test]` test, created from the test database
+///
+#[async_trait]
+pub trait PgTestArg: Sized {
+    ///
+    /// Create the parameter for the test database
+    ///
+    async fn from_database(database: &PgDatabaseGuard) -> PgResult<Self>;
+}
+
+#[async_trait]
+impl PgTestArg for String {
+    async fn from_database(database: &PgDatabaseGuard) -> PgResult<Self> {
+        Ok(database.uri())
+    }
+}
+
+#[cfg(feature = "rt_tokio_migrate")]
+#[async_trait]
+impl PgTestArg for sqlx_tokio::PgPool {
+    async fn from_database(database: &PgDatabaseGuard) -> PgResult<Self> {
+        sqlx_tokio::PgPool::connect(&database.uri())
+            .await
+            .map_err(|e| PgEmbedError {
+                error_type: PgEmbedErrorType::SqlQueryError,
+                source: Some(Box::new(e)),
+                message: None,
+            })
+    }
+}
+
+///
+/// Database of a `#[pg_embed::test]` test
+///
+/// Keeps the shared server running while the test runs.
+///
+pub struct PgTestDatabase {
+    /// the test database
+    database: PgDatabaseGuard,
+    /// factory of the shared server's test databases
+    _factory: Arc<DatabaseFactory>,
+}
+
+impl Deref for PgTestDatabase {
+    type Target = PgDatabaseGuard;
+
+    fn deref(&self) -> &Self::Target {
+        &self.database
+    }
+}
+
+///
+/// Create a fresh test database
+///
+/// Starts the shared postgresql server and prepares the template database on first use.
+///
+pub async fn test_database() -> PgResult<PgTestDatabase> {
+    let factory = {
+        let mut test_factory = TEST_FACTORY.lock().await;
+        match test_factory.upgrade() {
+            Some(factory) => factory,
+            None => {
+                let factory = Arc::new(
+                    DatabaseFactory::new(PgEmbed::global().await?, TEST_TEMPLATE_DB).await?,
+                );
+                *test_factory = Arc::downgrade(&factory);
+                factory
+            }
+        }
+    };
+    Ok(PgTestDatabase {
+        database: factory.create().await?,
+        _factory: factory,
+    })
+}
+
+///
+/// Drop a test database
+///
+pub async fn teardown(database: PgTestDatabase) {
+    let name = database.name().to_string();
+    if let Err(e) = database.database.drop_database().await {
+        warn!("could not drop test database {}: {}", name, e);
+    }
+}
+
+///
+/// Run a test future, catching its panic
+///
+pub async fn catch_unwind<F: Future>(future: F) -> std::thread::Result<F::Output> {
+    AssertUnwindSafe(future).catch_unwind().await
+}
+
+///
+/// Unused local port
+///
+pub(crate) fn free_port() -> PgResult<u16> {
+    std::net::TcpListener::bind(("127.0.0.1", 0))
+        .and_then(|listener| listener.local_addr())
+        .map(|addr| addr.port())
+        .map_err(|e| PgEmbedError {
+            error_type: PgEmbedErrorType::PgStartFailure,
+            source: Some(Box::new(e)),
+            message: Some(String::from("could not find a free port")),
+        })
+}
@@ -0,0 +1,24 @@
+use pg_embed::bench::PgBenchResult;
+
+#[test]
+fn parse_pgbench_output() {
+    let output = "\
+transaction type: <builtin: TPC-B (sort of)>
+scaling factor: 1
+query mode: simple
+number of clients: 4
+number of threads: 2
+number of transactions per client: 1000
+number of transactions actually processed: 4000/4000
+latency average = 2.345 ms
+latency stddev = 0.512 ms
+tps = 1705.123456 (including connections establishing)
+tps = 1710.987654 (excluding connections establishing)
+";
+    let result = PgBenchResult::parse(output).unwrap();
+    assert_eq!(result.tps, 1710.987654);
+    assert_eq!(result.latency_average_ms, Some(2.345));
+    assert_eq!(result.latency_stddev_ms, Some(0.512));
+    assert_eq!(result.transactions, Some(4000));
+    assert!(PgBenchResult::parse("pgbench: error").is_none());
+}
@@ -0,0 +1,34 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+
+use pg_embed::pg_commands::PgCommand;
+
+#[test]
+fn child_env_drops_inherited_pg_variables() {
+    std::env::set_var("PGPORT", "6543");
+    std::env::set_var("PGDATA", "/tmp/pg-embed-foreign-cluster");
+    let mut env = HashMap::new();
+    env.insert("TZ".to_string(), "UTC".to_string());
+
+    let vars = PgCommand::child_env(&env, false);
+    assert!(!vars.contains_key(&OsString::from("PGPORT")));
+    assert!(!vars.contains_key(&OsString::from("PGDATA")));
+    assert_eq!(
+        Some(&OsString::from("UTC")),
+        vars.get(&OsString::from("TZ"))
+    );
+
+    let vars = PgCommand::child_env(&env, true);
+    assert_eq!(
+        Some(&OsString::from("6543")),
+        vars.get(&OsString::from("PGPORT"))
+    );
+    assert_eq!(
+        Some(&OsString::from("/tmp/pg-embed-foreign-cluster")),
+        vars.get(&OsString::from("PGDATA"))
+    );
+    assert_eq!(
+        Some(&OsString::from("UTC")),
+        vars.get(&OsString::from("TZ"))
+    );
+}
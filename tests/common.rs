@@ -18,14 +18,15 @@ pub async fn setup(
         .is_test(true)
         .try_init();
     let pg_settings = PgSettings {
-        database_dir,
+        database_dir: Some(database_dir),
         port,
         user: "postgres".to_string(),
-        password: "password".to_string(),
+        password: "password".into(),
         auth_method: PgAuthMethod::MD5,
         persistent,
         timeout: Some(Duration::from_secs(10)),
         migration_dir,
+        ..Default::default()
     };
     let fetch_settings = PgFetchSettings {
         version: PG_V13,
@@ -0,0 +1,132 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use pg_embed::pg_errors::{PgEmbedError, PgEmbedErrorType};
+use pg_embed::pg_fetch::{PgChecksum, PgFetchSettings};
+use pg_embed::pg_http::{PgHttpClient, PgHttpRequest, PgHttpResponse, STATUS_PARTIAL_CONTENT};
+use pg_embed::pg_types::PgResult;
+
+const ARCHIVE_SIZE: usize = 3 * 1024 * 1024;
+const BODY_CHUNK_SIZE: usize = 64 * 1024;
+
+struct MockResponse {
+    status: u16,
+    content_range: Option<String>,
+    content_length: u64,
+    chunks: VecDeque<PgResult<Bytes>>,
+}
+
+#[async_trait]
+impl PgHttpResponse for MockResponse {
+    fn status(&self) -> u16 {
+        self.status
+    }
+
+    fn content_length(&self) -> Option<u64> {
+        Some(self.content_length)
+    }
+
+    fn header(&self, name: &str) -> Option<String> {
+        if name.eq_ignore_ascii_case("Content-Range") {
+            self.content_range.clone()
+        } else {
+            None
+        }
+    }
+
+    async fn chunk(&mut self) -> PgResult<Option<Bytes>> {
+        self.chunks.pop_front().transpose()
+    }
+}
+
+///
+/// Serves byte ranges of `archive`, the first request of the range starting at
+/// `failing_start` breaks off after half of the range
+///
+struct MockClient {
+    archive: Vec<u8>,
+    failing_start: u64,
+    failures: AtomicUsize,
+    full_requests: AtomicUsize,
+}
+
+#[async_trait]
+impl PgHttpClient for MockClient {
+    async fn send(&self, request: PgHttpRequest) -> PgResult<Box<dyn PgHttpResponse>> {
+        let range = request
+            .headers
+            .iter()
+            .find(|(name, _)| name == "Range")
+            .map(|(_, value)| value.trim_start_matches("bytes=").to_string());
+        let total = self.archive.len() as u64;
+        let (start, end, status) = match range {
+            Some(range) => {
+                let mut bounds = range.split('-');
+                let start: u64 = bounds.next().unwrap().parse().unwrap();
+                let end = match bounds.next() {
+                    Some(end) if !end.is_empty() => end.parse().unwrap(),
+                    _ => total - 1,
+                };
+                (start, end, STATUS_PARTIAL_CONTENT)
+            }
+            None => {
+                self.full_requests.fetch_add(1, Ordering::SeqCst);
+                (0, total - 1, 200)
+            }
+        };
+        let body = &self.archive[start as usize..=end as usize];
+        let mut chunks: VecDeque<PgResult<Bytes>> = body
+            .chunks(BODY_CHUNK_SIZE)
+            .map(|chunk| Ok(Bytes::copy_from_slice(chunk)))
+            .collect();
+        if start == self.failing_start && self.failures.fetch_add(1, Ordering::SeqCst) == 0 {
+            chunks.truncate(chunks.len() / 2);
+            chunks.push_back(Err(PgEmbedError {
+                error_type: PgEmbedErrorType::DownloadFailure,
+                source: None,
+                message: Some("connection reset".to_string()),
+            }));
+        }
+        Ok(Box::new(MockResponse {
+            status,
+            content_range: Some(format!("bytes {}-{}/{}", start, end, total)),
+            content_length: end - start + 1,
+            chunks,
+        }))
+    }
+}
+
+#[tokio::test]
+async fn parallel_download_retries_failed_range() -> Result<(), PgEmbedError> {
+    let archive: Vec<u8> = (0..ARCHIVE_SIZE).map(|i| (i % 251) as u8).collect();
+    // the second of two ranges fails once
+    let failing_start = (ARCHIVE_SIZE / 2 + 1) as u64;
+    let client = Arc::new(MockClient {
+        archive: archive.clone(),
+        failing_start,
+        failures: AtomicUsize::new(0),
+        full_requests: AtomicUsize::new(0),
+    });
+    let fetch_settings = PgFetchSettings {
+        download_concurrency: 2,
+        max_retries: 1,
+        backoff: Duration::from_millis(1),
+        fetch_timeout: Some(Duration::from_secs(10)),
+        checksum: PgChecksum::None,
+        http_client: Some(client.clone()),
+        ..Default::default()
+    };
+    let dir = tempfile::tempdir().unwrap();
+    let archive_path = dir.path().join("archive");
+    fetch_settings.fetch_postgres(&archive_path).await?;
+
+    assert_eq!(archive, std::fs::read(&archive_path).unwrap());
+    assert_eq!(1, client.failures.load(Ordering::SeqCst));
+    // the failed range was retried instead of falling back to a single stream
+    assert_eq!(0, client.full_requests.load(Ordering::SeqCst));
+    Ok(())
+}
@@ -0,0 +1,43 @@
+use pg_embed::pg_enums::PgDumpFormat;
+use pg_embed::pg_errors::PgEmbedError;
+use pg_embed::postgres::normalize_schema_dump;
+
+#[test]
+fn detect_dump_format() -> Result<(), PgEmbedError> {
+    let dir = tempfile::tempdir().unwrap();
+    let custom = dir.path().join("db.dump");
+    std::fs::write(&custom, b"PGDMP\x01\x0e\x00").unwrap();
+    assert_eq!(PgDumpFormat::detect(&custom)?, PgDumpFormat::Custom);
+
+    let plain = dir.path().join("db.sql");
+    std::fs::write(&plain, "CREATE TABLE users (id int);\n").unwrap();
+    assert_eq!(PgDumpFormat::detect(&plain)?, PgDumpFormat::Plain);
+
+    let tar = dir.path().join("db.tar");
+    let mut builder = tar::Builder::new(std::fs::File::create(&tar).unwrap());
+    let mut header = tar::Header::new_ustar();
+    header.set_path("toc.dat").unwrap();
+    header.set_size(0);
+    header.set_cksum();
+    builder.append(&header, &[][..]).unwrap();
+    builder.finish().unwrap();
+    assert_eq!(PgDumpFormat::detect(&tar)?, PgDumpFormat::Tar);
+
+    assert_eq!(PgDumpFormat::detect(dir.path())?, PgDumpFormat::Directory);
+    Ok(())
+}
+
+#[test]
+fn normalize_schema() {
+    let dump = "--\n-- PostgreSQL database dump\n--\n\n\\restrict abc\n\
+                -- Dumped from database version 15.3\n\n\
+                SET statement_timeout = 0;\n\
+                SELECT pg_catalog.set_config('search_path', '', false);\n\n\n\
+                CREATE TABLE public.users (\n    id integer NOT NULL\n);\n\n\n\n\
+                CREATE INDEX users_id ON public.users USING btree (id);   \n\n";
+    assert_eq!(
+        normalize_schema_dump(dump),
+        "CREATE TABLE public.users (\n    id integer NOT NULL\n);\n\n\
+         CREATE INDEX users_id ON public.users USING btree (id);\n"
+    );
+}
@@ -0,0 +1,57 @@
+use std::error::Error;
+
+use pg_embed::pg_errors::{PgEmbedError, PgEmbedErrorType};
+
+fn assert_send_sync_static<T: Error + Send + Sync + 'static>() {}
+
+#[test]
+fn error_is_send_sync_static() {
+    assert_send_sync_static::<PgEmbedError>();
+}
+
+#[test]
+fn error_source_and_display() {
+    let err = PgEmbedError {
+        error_type: PgEmbedErrorType::ReadFileError,
+        source: Some(Box::new(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "no such file",
+        ))),
+        message: Some("could not read pg_embed.toml".to_string()),
+    };
+    assert_eq!(
+        "could not read file: could not read pg_embed.toml: no such file",
+        err.to_string()
+    );
+    let source = err.source().map(|source| source.to_string());
+    assert_eq!(Some("no such file".to_string()), source);
+    assert_eq!(&PgEmbedErrorType::ReadFileError, err.kind());
+
+    let boxed: Box<dyn Error + Send + Sync> = Box::new(err);
+    assert!(boxed.downcast_ref::<PgEmbedError>().is_some());
+}
+
+#[test]
+fn error_without_source() {
+    let err = PgEmbedError {
+        error_type: PgEmbedErrorType::PgTimedOutError,
+        source: None,
+        message: None,
+    };
+    assert!(err.source().is_none());
+    assert_eq!("operation timed out", err.to_string());
+}
+
+#[test]
+fn error_kind_with_context() {
+    let err = PgEmbedError {
+        error_type: PgEmbedErrorType::PortAlreadyInUse(5432),
+        source: None,
+        message: None,
+    };
+    assert!(matches!(
+        err.kind(),
+        PgEmbedErrorType::PortAlreadyInUse(5432)
+    ));
+    assert_eq!("port 5432 is already in use", err.to_string());
+}
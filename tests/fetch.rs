@@ -0,0 +1,70 @@
+use pg_embed::pg_errors::PgEmbedError;
+use pg_embed::pg_fetch::{PgVersionReq, PostgresVersion, PG_V13, PG_V15, PG_V9};
+use pg_embed::pg_provider::{LocalArchiveProvider, PgBinaryProvider};
+
+#[test]
+fn parse_version_req() -> Result<(), PgEmbedError> {
+    assert_eq!("latest".parse::<PgVersionReq>()?, PgVersionReq::Latest);
+    assert_eq!("13".parse::<PgVersionReq>()?, PgVersionReq::Major(13));
+    assert_eq!("13.*".parse::<PgVersionReq>()?, PgVersionReq::Major(13));
+    assert_eq!("13.9".parse::<PgVersionReq>()?, PgVersionReq::Minor(13, 9));
+    assert_eq!(
+        "13.9.*".parse::<PgVersionReq>()?,
+        PgVersionReq::Minor(13, 9)
+    );
+    assert!("13.x".parse::<PgVersionReq>().is_err());
+    assert!("13.9.0.1".parse::<PgVersionReq>().is_err());
+    Ok(())
+}
+
+#[test]
+fn match_version_req() -> Result<(), PgEmbedError> {
+    assert!(PgVersionReq::Latest.matches(&PG_V15));
+    assert!(PgVersionReq::Major(13).matches(&PG_V13));
+    assert!(!PgVersionReq::Major(13).matches(&PostgresVersion::parse("14.6.0")?));
+    assert!(PgVersionReq::Minor(13, 9).matches(&PG_V13));
+    assert!(!PgVersionReq::Minor(13, 9).matches(&PostgresVersion::parse("13.8.0")?));
+    Ok(())
+}
+
+#[test]
+fn parse_postgres_version() -> Result<(), PgEmbedError> {
+    let version = PostgresVersion::parse("15.4.0")?;
+    assert_eq!(version.major(), 15);
+    assert_eq!(version.minor(), 4);
+    assert_eq!(version.patch(), 0);
+    assert_eq!(version.to_string(), "15.4.0");
+    assert_eq!(PostgresVersion::parse("15.4")?, version);
+    assert!(PostgresVersion::parse("16.0.0-beta1").is_err());
+    assert!(PostgresVersion::parse("16").is_err());
+    Ok(())
+}
+
+#[test]
+fn order_postgres_versions() -> Result<(), PgEmbedError> {
+    assert!(PG_V9 < PG_V13);
+    assert!(PostgresVersion::parse("13.10.0")? > PostgresVersion::parse("13.9.0")?);
+    assert!(!PG_V9.supports_scram());
+    assert!(PG_V13.supports_scram());
+    assert!(!PG_V13.supports_icu_locale_provider());
+    assert!(PG_V15.supports_icu_locale_provider());
+    Ok(())
+}
+
+#[test]
+fn local_archive_cache_key() {
+    let dir = tempfile::tempdir().unwrap();
+    let v15 = dir.path().join("v15").join("postgres.txz");
+    let v16 = dir.path().join("v16").join("postgres.txz");
+    for archive in [&v15, &v16].iter() {
+        std::fs::create_dir_all(archive.parent().unwrap()).unwrap();
+        std::fs::write(archive, b"archive").unwrap();
+    }
+    let key = LocalArchiveProvider::new(&v15).cache_key();
+    assert!(key.starts_with("local/postgres.txz-"));
+    assert_ne!(key, LocalArchiveProvider::new(&v16).cache_key());
+
+    // an updated archive at the same path gets a new cache entry
+    std::fs::write(&v15, b"updated archive").unwrap();
+    assert_ne!(key, LocalArchiveProvider::new(&v15).cache_key());
+}
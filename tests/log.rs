@@ -0,0 +1,67 @@
+use pg_embed::pg_enums::PgLogLevel;
+use pg_embed::pg_log::{parse_captured_statements, parse_log_events, parse_slow_queries};
+
+#[test]
+fn parse_statement_log() {
+    let log = "\
+2024-01-01 12:00:00.001 UTC,\"postgres\",\"test\",4242,\"[local]\",65a1.1092,1,\"idle\",2024-01-01 12:00:00 UTC,3/2,0,LOG,00000,\"statement: SELECT 'a,b'
+FROM \"\"t\"\"\",,,,,,,,,\"psql\",\"client backend\",,0
+2024-01-01 12:00:00.002 UTC,\"postgres\",\"test\",4243,\"127.0.0.1:5000\",65a1.1093,2,\"SELECT\",2024-01-01 12:00:00 UTC,4/2,0,LOG,00000,\"execute sqlx_s_1: SELECT $1\",\"parameters: $1 = '42'\",,,,,,,,\"\",\"client backend\",,0
+2024-01-01 12:00:00.003 UTC,,,4200,,65a1.1000,1,,2024-01-01 12:00:00 UTC,,0,LOG,00000,\"database system is ready to accept connections\",,,,,,,,,\"\",\"postmaster\",,0
+";
+    let statements = parse_captured_statements(log);
+    assert_eq!(statements.len(), 2);
+    assert_eq!(statements[0].statement, "SELECT 'a,b'\nFROM \"t\"");
+    assert_eq!(statements[0].database, "test");
+    assert_eq!(statements[0].backend_pid, Some(4242));
+    assert_eq!(statements[0].parameters, None);
+    assert_eq!(statements[1].statement, "SELECT $1");
+    assert_eq!(statements[1].parameters.as_deref(), Some("$1 = '42'"));
+}
+
+#[test]
+fn parse_log_entries() {
+    let log = "\
+2024-01-01 12:00:00.001 UTC,\"postgres\",\"test\",4242,\"[local]\",65a1.1092,1,\"INSERT\",2024-01-01 12:00:00 UTC,3/2,731,ERROR,23505,\"duplicate key value violates unique constraint \"\"users_pkey\"\"\",\"Key (id)=(1) already exists.\",,,,,\"INSERT INTO users VALUES (1)\",,,\"psql\",\"client backend\",,0
+2024-01-01 12:00:00.003 UTC,,,4200,,65a1.1000,1,,2024-01-01 12:00:00 UTC,,0,LOG,00000,\"database system is ready to accept connections\",,,,,,,,,\"\",\"postmaster\",,0
+";
+    let events = parse_log_events(log);
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].level, PgLogLevel::Error);
+    assert_eq!(events[0].sqlstate.as_deref(), Some("23505"));
+    assert_eq!(
+        events[0].message,
+        "duplicate key value violates unique constraint \"users_pkey\""
+    );
+    assert_eq!(
+        events[0].detail.as_deref(),
+        Some("Key (id)=(1) already exists.")
+    );
+    assert_eq!(events[0].backend_pid, Some(4242));
+    assert_eq!(events[1].level, PgLogLevel::Log);
+    assert_eq!(events[1].sqlstate, None);
+    assert!(PgLogLevel::Warning > PgLogLevel::Log);
+}
+
+#[test]
+fn parse_slow_query_log() {
+    let log = "\
+2024-01-01 12:00:01.000 UTC,\"postgres\",\"test\",4242,\"[local]\",65a1.1092,1,\"SELECT\",2024-01-01 12:00:00 UTC,3/2,0,LOG,00000,\"duration: 1001.500 ms  plan:
+Query Text: SELECT pg_sleep(1)
+Result  (cost=0.00..0.01 rows=1 width=4)\",,,,,,,,,\"psql\",\"client backend\",,0
+2024-01-01 12:00:01.001 UTC,\"postgres\",\"test\",4242,\"[local]\",65a1.1092,2,\"SELECT\",2024-01-01 12:00:00 UTC,3/2,0,LOG,00000,\"duration: 1001.800 ms  statement: SELECT pg_sleep(1)\",,,,,,,,,\"psql\",\"client backend\",,0
+2024-01-01 12:00:02.000 UTC,\"postgres\",\"test\",4243,\"127.0.0.1:5000\",65a1.1093,1,\"SELECT\",2024-01-01 12:00:00 UTC,4/2,0,LOG,00000,\"duration: 2002.000 ms  execute sqlx_s_1: SELECT pg_sleep($1)\",\"parameters: $1 = '2'\",,,,,,,,\"\",\"client backend\",,0
+";
+    let slow_queries = parse_slow_queries(log);
+    assert_eq!(slow_queries.len(), 2);
+    assert_eq!(slow_queries[0].duration_ms, 2002.0);
+    assert_eq!(slow_queries[0].statement, "SELECT pg_sleep($1)");
+    assert_eq!(slow_queries[0].parameters.as_deref(), Some("$1 = '2'"));
+    assert_eq!(slow_queries[0].plan, None);
+    assert_eq!(slow_queries[1].statement, "SELECT pg_sleep(1)");
+    assert!(slow_queries[1]
+        .plan
+        .as_deref()
+        .unwrap()
+        .starts_with("Query Text: SELECT pg_sleep(1)"));
+}
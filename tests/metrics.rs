@@ -0,0 +1,25 @@
+use std::collections::BTreeMap;
+
+use pg_embed::pg_metrics::{PgDatabaseMetrics, PgMetrics};
+
+#[test]
+fn metrics_prometheus_format() {
+    let mut counters = BTreeMap::new();
+    counters.insert("xact_commit".to_string(), 42);
+    let mut connections = BTreeMap::new();
+    connections.insert("idle in transaction".to_string(), 2);
+    let metrics = PgMetrics {
+        databases: vec![PgDatabaseMetrics {
+            database: "test\"db".to_string(),
+            backends: 3,
+            counters,
+        }],
+        connections,
+    };
+    let text = metrics.to_prometheus();
+    assert!(text.contains("# TYPE pg_stat_database_xact_commit counter\n"));
+    assert!(text.contains("pg_stat_database_numbackends{datname=\"test\\\"db\"} 3\n"));
+    assert!(text.contains("pg_stat_database_xact_commit{datname=\"test\\\"db\"} 42\n"));
+    assert!(text.contains("pg_stat_activity_count{state=\"idle in transaction\"} 2\n"));
+    assert!(!text.contains("pg_stat_database_deadlocks{"));
+}
@@ -87,3 +87,62 @@ async fn db_migration() -> Result<(), PgEmbedError> {
 
     Ok(())
 }
+
+#[tokio::test]
+#[serial]
+async fn db_runtime_config() -> Result<(), PgEmbedError> {
+    let mut pg = common::setup(5432, PathBuf::from("data_test/db"), false, None).await?;
+    pg.start_db().await?;
+
+    pg.set_runtime_config("work_mem", "8MB").await?;
+    assert_eq!("8MB", pg.get_config("work_mem").await?);
+
+    let res = pg.get_config("work_mem; DROP TABLE x").await.err();
+    assert_eq!(
+        Some(PgEmbedErrorType::InvalidConfig),
+        res.map(|e| e.error_type)
+    );
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn db_migration_status_is_read_only() -> Result<(), PgEmbedError> {
+    let mut pg = common::setup(
+        5432,
+        PathBuf::from("data_test/db"),
+        false,
+        Some(PathBuf::from("migration_test")),
+    )
+    .await?;
+    pg.start_db().await?;
+    let db_name = "status";
+    pg.create_database(&db_name).await?;
+
+    let status = pg.migration_status(&db_name).await?;
+    assert!(!status.is_empty());
+    assert!(status.iter().all(|migration| migration.pending));
+
+    let mut conn = PgConnection::connect(&pg.full_db_uri(&db_name))
+        .await
+        .map_err(|_| PgEmbedError {
+            error_type: PgEmbedErrorType::SqlQueryError,
+            source: None,
+            message: None,
+        })?;
+    let tracked: bool =
+        sqlx_tokio::query_scalar("SELECT to_regclass('_sqlx_migrations') IS NOT NULL")
+            .fetch_one(&mut conn)
+            .await
+            .map_err(|_| PgEmbedError {
+                error_type: PgEmbedErrorType::SqlQueryError,
+                source: None,
+                message: None,
+            })?;
+    assert!(!tracked);
+
+    pg.migrate(&db_name).await?;
+    let status = pg.migration_status(&db_name).await?;
+    assert!(status.iter().all(|migration| !migration.pending));
+    Ok(())
+}
@@ -0,0 +1,204 @@
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+use pg_embed::pg_access::{PgAccess, PG_EMBED_CACHE_DIR_ENV};
+use pg_embed::pg_errors::PgEmbedError;
+use pg_embed::pg_fetch::{PgCacheBudget, PgFetchSettings};
+use serial_test::serial;
+
+fn exe(name: &str) -> String {
+    format!("{}{}", name, std::env::consts::EXE_SUFFIX)
+}
+
+#[test]
+fn executable_path() {
+    let installation_dir = Path::new("pg");
+    assert_eq!(
+        PgAccess::executable_path(installation_dir, "pg_ctl"),
+        installation_dir.join("bin").join(exe("pg_ctl"))
+    );
+    if cfg!(windows) {
+        assert_eq!(PgAccess::executable_name("initdb"), "initdb.exe");
+    } else {
+        assert_eq!(PgAccess::executable_name("initdb"), "initdb");
+    }
+}
+
+#[tokio::test]
+async fn executables_in_cache_dir() -> Result<(), PgEmbedError> {
+    let cache_dir = PathBuf::from("data_test").join("access_cache");
+    let pg_access = PgAccess::new(&PgFetchSettings::default(), None, Some(&cache_dir)).await?;
+    let bin_dir = cache_dir.join("bin");
+    assert_eq!(pg_access.pg_ctl_exe, bin_dir.join(exe("pg_ctl")));
+    assert_eq!(pg_access.init_db_exe, bin_dir.join(exe("initdb")));
+    assert_eq!(pg_access.postgres_exe, bin_dir.join(exe("postgres")));
+    Ok(())
+}
+
+#[tokio::test]
+async fn stop_db_command_sync() -> Result<(), PgEmbedError> {
+    let cache_dir = PathBuf::from("data_test").join("access_cache");
+    let pg_access = PgAccess::new(&PgFetchSettings::default(), None, Some(&cache_dir)).await?;
+    let command = pg_access.stop_db_command_sync(&pg_access.database_dir, &Default::default());
+    assert_eq!(command.get_program(), pg_access.pg_ctl_exe.as_os_str());
+    let args: Vec<_> = command.get_args().collect();
+    assert_eq!(
+        args,
+        vec![
+            OsStr::new("stop"),
+            OsStr::new("-w"),
+            OsStr::new("-D"),
+            pg_access.database_dir.as_os_str()
+        ]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn cache_root_from_settings() -> Result<(), PgEmbedError> {
+    let cache_root = PathBuf::from("data_test").join("access_cache_root");
+    let fetch_settings = PgFetchSettings {
+        cache_dir: Some(cache_root.clone()),
+        ..Default::default()
+    };
+    let pg_access = PgAccess::new(&fetch_settings, None, None).await?;
+    assert_eq!(pg_access.cache_root, cache_root);
+    assert!(pg_access.cache_dir.starts_with(&cache_root));
+    Ok(())
+}
+
+#[tokio::test]
+async fn purge_cache_root_from_settings() -> Result<(), PgEmbedError> {
+    let cache_root = tempfile::tempdir().unwrap();
+    let fetch_settings = PgFetchSettings {
+        cache_dir: Some(cache_root.path().to_path_buf()),
+        ..Default::default()
+    };
+    let pg_access = PgAccess::new(&fetch_settings, None, None).await?;
+    std::fs::create_dir_all(pg_access.cache_dir.join("bin")).unwrap();
+
+    let cached = PgAccess::list_cached(&fetch_settings).await?;
+    assert_eq!(
+        vec![pg_access.cache_dir.clone()],
+        cached
+            .into_iter()
+            .map(|installation| installation.path)
+            .collect::<Vec<_>>()
+    );
+    PgAccess::purge_version(
+        &fetch_settings,
+        &fetch_settings.version,
+        &fetch_settings.platform(),
+    )
+    .await?;
+    assert!(!pg_access.cache_dir.exists());
+    assert!(PgAccess::list_cached(&fetch_settings).await?.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn user_cache_dir_is_not_owned() -> Result<(), PgEmbedError> {
+    let user_dir = PathBuf::from("data_test").join("access_user_cache");
+    std::fs::create_dir_all(&user_dir).unwrap();
+    let pg_access = PgAccess::new(&PgFetchSettings::default(), None, Some(&user_dir)).await?;
+    assert!(!pg_access.is_owned_cache());
+
+    let fetch_settings = PgFetchSettings {
+        cache_dir: Some(PathBuf::from("data_test").join("access_owned_cache")),
+        ..Default::default()
+    };
+    let pg_access = PgAccess::new(&fetch_settings, None, None).await?;
+    assert!(pg_access.is_owned_cache());
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn purge_keeps_user_content() -> Result<(), PgEmbedError> {
+    let cache_root = PathBuf::from("data_test").join("access_user_root");
+    let user_installation = cache_root.join("user");
+    std::fs::create_dir_all(user_installation.join("bin")).unwrap();
+    std::fs::write(cache_root.join("notes.txt"), "keep").unwrap();
+    std::env::set_var(PG_EMBED_CACHE_DIR_ENV, &cache_root);
+    let fetch_settings = PgFetchSettings::default();
+    let pg_access = PgAccess::new(&fetch_settings, None, None).await?;
+    std::fs::create_dir_all(pg_access.cache_dir.join("bin")).unwrap();
+
+    let purged = PgAccess::purge(&fetch_settings).await;
+    std::env::remove_var(PG_EMBED_CACHE_DIR_ENV);
+    purged?;
+    assert!(!pg_access.cache_dir.exists());
+    assert!(user_installation.join("bin").exists());
+    assert!(cache_root.join("notes.txt").exists());
+    Ok(())
+}
+
+fn fake_installation(cache_root: &Path, name: &str, last_used: u64) -> PathBuf {
+    let installation = cache_root.join(name);
+    std::fs::create_dir_all(installation.join("bin")).unwrap();
+    std::fs::write(installation.join(".pg-embed-owned"), b"").unwrap();
+    std::fs::write(installation.join(".last-use"), last_used.to_string()).unwrap();
+    installation
+}
+
+#[tokio::test]
+async fn cache_budget_keeps_installations_in_use() -> Result<(), PgEmbedError> {
+    let cache_root = tempfile::tempdir().unwrap();
+    let in_use = fake_installation(cache_root.path(), "in-use", 1);
+    let unused = fake_installation(cache_root.path(), "unused", 2);
+    let current = fake_installation(cache_root.path(), "current", 3);
+    let fetch_settings = PgFetchSettings {
+        cache_dir: Some(cache_root.path().to_path_buf()),
+        ..Default::default()
+    };
+    // the least recently used installation is held by another living instance
+    let _other = PgAccess::new(&fetch_settings, None, Some(&in_use)).await?;
+    let pg_access = PgAccess::new(&fetch_settings, None, Some(&current)).await?;
+
+    let budget = PgCacheBudget {
+        max_installations: Some(1),
+        ..Default::default()
+    };
+    let evicted = pg_access.enforce_cache_budget(&budget).await?;
+    let evicted: Vec<PathBuf> = evicted.into_iter().map(|i| i.path).collect();
+    assert_eq!(evicted, vec![unused.clone()]);
+    assert!(!unused.exists());
+    assert!(in_use.join("bin").exists());
+    assert!(current.join("bin").exists());
+    Ok(())
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn snapshot_keeps_tablespace_links() -> Result<(), PgEmbedError> {
+    let dir = tempfile::tempdir().unwrap();
+    let database_dir = dir.path().join("db");
+    let tablespace_dir = dir.path().join("tablespace");
+    std::fs::create_dir_all(database_dir.join("pg_tblspc")).unwrap();
+    std::fs::create_dir_all(&tablespace_dir).unwrap();
+    std::fs::write(database_dir.join("PG_VERSION"), "15\n").unwrap();
+    std::fs::write(tablespace_dir.join("16385"), "relation").unwrap();
+    let tablespace_link = Path::new("pg_tblspc").join("16384");
+    std::os::unix::fs::symlink(&tablespace_dir, database_dir.join(&tablespace_link)).unwrap();
+    let cache_dir = PathBuf::from("data_test").join("access_cache");
+    let pg_access = PgAccess::new(
+        &PgFetchSettings::default(),
+        Some(&database_dir),
+        Some(&cache_dir),
+    )
+    .await?;
+
+    pg_access.snapshot_cluster("tablespace").await?;
+    let snapshot_link = pg_access.snapshot_dir("tablespace").join(&tablespace_link);
+    assert!(std::fs::symlink_metadata(&snapshot_link)
+        .unwrap()
+        .file_type()
+        .is_symlink());
+    assert_eq!(std::fs::read_link(&snapshot_link).unwrap(), tablespace_dir);
+
+    pg_access.restore_cluster_snapshot("tablespace").await?;
+    let restored_link = database_dir.join(&tablespace_link);
+    assert_eq!(std::fs::read_link(&restored_link).unwrap(), tablespace_dir);
+    assert!(tablespace_dir.join("16385").exists());
+    Ok(())
+}
@@ -6,7 +6,7 @@ use tokio::sync::Mutex;
 
 use env_logger::Env;
 use pg_embed::pg_access::PgAccess;
-use pg_embed::pg_enums::{PgAuthMethod, PgServerStatus};
+use pg_embed::pg_enums::{PgAuthMethod, PgPassword, PgServerStatus};
 use pg_embed::pg_errors::{PgEmbedError, PgEmbedErrorType};
 use pg_embed::pg_fetch::{PgFetchSettings, PG_V13};
 use pg_embed::postgres::{PgEmbed, PgSettings};
@@ -56,7 +56,7 @@ async fn postgres_server_drop() -> Result<(), PgEmbedError> {
 #[tokio::test]
 #[serial]
 async fn postgres_server_multiple_concurrent() -> Result<(), PgEmbedError> {
-    PgAccess::purge().await?;
+    PgAccess::purge(&PgFetchSettings::default()).await?;
 
     let tasks = vec![
         common::setup(5432, PathBuf::from("data_test/db1"), false, None),
@@ -145,14 +145,15 @@ async fn postgres_server_timeout() -> Result<(), PgEmbedError> {
         .is_test(true)
         .try_init();
     let mut pg_settings = PgSettings {
-        database_dir,
+        database_dir: Some(database_dir),
         port: 5432,
         user: "postgres".to_string(),
-        password: "password".to_string(),
+        password: "password".into(),
         auth_method: PgAuthMethod::MD5,
         persistent: false,
         timeout: Some(Duration::from_secs(10)),
         migration_dir: None,
+        ..Default::default()
     };
     let fetch_settings = PgFetchSettings {
         version: PG_V13,
@@ -166,3 +167,57 @@ async fn postgres_server_timeout() -> Result<(), PgEmbedError> {
 
     Ok(())
 }
+
+#[tokio::test]
+#[serial]
+async fn postgres_server_temporary_database_dir() -> Result<(), PgEmbedError> {
+    let pg_settings = PgSettings {
+        database_dir: None,
+        persistent: true,
+        ..Default::default()
+    };
+    let fetch_settings = PgFetchSettings {
+        version: PG_V13,
+        ..Default::default()
+    };
+    let database_dir;
+    {
+        let pg = PgEmbed::new(pg_settings, fetch_settings).await?;
+        assert!(pg.pg_access.is_temporary());
+        database_dir = pg.pg_access.database_dir.clone();
+        assert!(database_dir.exists());
+    }
+    assert!(!database_dir.exists());
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn postgres_server_random_password() -> Result<(), PgEmbedError> {
+    let pg_settings = PgSettings {
+        password: PgPassword::Random(16),
+        ..Default::default()
+    };
+    let pg = PgEmbed::new(pg_settings, PgFetchSettings::default()).await?;
+    assert_eq!(16, pg.password().len());
+    assert!(pg.db_uri.contains(pg.password()));
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn postgres_server_init_non_empty_database_dir() -> Result<(), PgEmbedError> {
+    let pg_settings = PgSettings {
+        database_dir: None,
+        ..Default::default()
+    };
+    let mut pg = PgEmbed::new(pg_settings, PgFetchSettings::default()).await?;
+    let database_dir = pg.pg_access.database_dir.clone();
+    std::fs::write(database_dir.join("leftover"), b"").unwrap();
+    let res = pg.init_db().await.err().map(|e| e.error_type);
+    assert_eq!(Some(PgEmbedErrorType::DataDirNotEmpty(database_dir)), res);
+
+    Ok(())
+}
@@ -0,0 +1,40 @@
+use std::time::Duration;
+
+use pg_embed::pg_seed::{PgSeedColumn, PgSeedGenerator, PgSeedSpec};
+
+#[test]
+fn generate_seed_csv() {
+    let spec = PgSeedSpec {
+        table: "events".to_string(),
+        rows: 3,
+        columns: vec![
+            PgSeedColumn {
+                name: "id".to_string(),
+                generator: PgSeedGenerator::Sequence(10),
+            },
+            PgSeedColumn {
+                name: "created_at".to_string(),
+                generator: PgSeedGenerator::Timestamp {
+                    start: 1_704_067_200,
+                    step: Duration::from_secs(90_061),
+                },
+            },
+            PgSeedColumn {
+                name: "key".to_string(),
+                generator: PgSeedGenerator::Uuid,
+            },
+        ],
+    };
+    let mut csv = Vec::new();
+    spec.write_csv(&mut csv).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    let rows: Vec<Vec<&str>> = csv.lines().map(|line| line.split(',').collect()).collect();
+    assert_eq!(rows.len(), 3);
+    assert_eq!(rows[0][0], "10");
+    assert_eq!(rows[2][0], "12");
+    assert_eq!(rows[0][1], "2024-01-01 00:00:00+00");
+    assert_eq!(rows[1][1], "2024-01-02 01:01:01+00");
+    let uuid = rows[0][2];
+    assert_eq!(uuid.len(), 36);
+    assert_eq!(&uuid[14..15], "4");
+}
@@ -0,0 +1,104 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use pg_embed::pg_config::PgConfig;
+use pg_embed::pg_enums::PgAuthMethod;
+use pg_embed::pg_errors::{PgEmbedError, PgEmbedErrorType};
+use pg_embed::postgres::PgSettings;
+use tempfile::TempDir;
+
+fn write_config(file_name: &str, content: &str) -> (TempDir, PathBuf) {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join(file_name);
+    std::fs::write(&path, content).unwrap();
+    (dir, path)
+}
+
+#[test]
+fn settings_from_toml_file() -> Result<(), PgEmbedError> {
+    let (_dir, path) = write_config(
+        "pg-embed-settings.toml",
+        r#"
+database_dir = "data_test/config_db"
+port = 5433
+auth_method = "scram-sha-256"
+timeout = "20s"
+
+[env]
+TZ = "UTC"
+"#,
+    );
+    let settings = PgSettings::from_file(&path)?;
+    assert_eq!(
+        Some(PathBuf::from("data_test/config_db")),
+        settings.database_dir
+    );
+    assert_eq!(5433, settings.port);
+    assert_eq!("postgres", settings.user);
+    assert!(matches!(settings.auth_method, PgAuthMethod::ScramSha256));
+    assert_eq!(Some(Duration::from_secs(20)), settings.timeout);
+    assert_eq!(Some(&"UTC".to_string()), settings.env.get("TZ"));
+    Ok(())
+}
+
+#[test]
+fn settings_from_yaml_file() -> Result<(), PgEmbedError> {
+    let (_dir, path) = write_config("pg-embed-settings.yml", "port: 5434\npersistent: true\n");
+    let settings = PgSettings::from_file(&path)?;
+    assert_eq!(5434, settings.port);
+    assert!(settings.persistent);
+    Ok(())
+}
+
+#[test]
+fn settings_from_unsupported_file() {
+    let (_dir, path) = write_config("pg-embed-settings.ini", "port = 5435\n");
+    let res = PgSettings::from_file(&path).err().map(|e| e.error_type);
+    assert_eq!(Some(PgEmbedErrorType::InvalidConfig), res);
+}
+
+#[test]
+fn settings_log_config() -> Result<(), PgEmbedError> {
+    let (_dir, path) = write_config(
+        "pg-embed-settings-log.toml",
+        r#"
+[config.log]
+collector = true
+directory = "server_log"
+rotation_size_kb = 1024
+"#,
+    );
+    let settings = PgSettings::from_file(&path)?;
+    let parameters = settings.config.parameters();
+    assert_eq!(Some(&"on".to_string()), parameters.get("logging_collector"));
+    assert_eq!(
+        Some(&"server_log".to_string()),
+        parameters.get("log_directory")
+    );
+    assert_eq!(
+        Some(&"1024".to_string()),
+        parameters.get("log_rotation_size")
+    );
+    assert_eq!(None, parameters.get("log_rotation_age"));
+    Ok(())
+}
+
+#[test]
+fn config_preload_library() {
+    let mut config = PgConfig::default();
+    config.settings.insert(
+        "shared_preload_libraries".to_string(),
+        "auto_explain".to_string(),
+    );
+    config.preload_library("pg_stat_statements");
+    config.preload_library("pg_stat_statements");
+    config.preload_library("auto_explain");
+    assert_eq!(
+        vec!["pg_stat_statements", "auto_explain"],
+        config.shared_preload_libraries
+    );
+    assert_eq!(
+        Some(&"auto_explain,pg_stat_statements".to_string()),
+        config.parameters().get("shared_preload_libraries")
+    );
+}
@@ -0,0 +1,20 @@
+use std::time::Duration;
+
+#[pg_embed::test]
+async fn injects_database_uri(db_uri: String) {
+    assert!(db_uri.starts_with("postgres://"));
+}
+
+#[pg_embed::test]
+async fn runs_without_parameter() {
+    tokio::time::sleep(Duration::from_millis(1)).await;
+}
+
+#[pg_embed::test]
+async fn returns_result(db_uri: String) -> Result<(), String> {
+    if db_uri.contains("pg_embed_test_template_") {
+        Ok(())
+    } else {
+        Err(format!("unexpected database {}", db_uri))
+    }
+}
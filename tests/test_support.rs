@@ -0,0 +1,23 @@
+use pg_embed::pg_errors::PgEmbedError;
+use pg_embed::test_support::{allocate, current_test_key};
+
+#[test]
+fn allocations_do_not_conflict() -> Result<(), PgEmbedError> {
+    let key = current_test_key();
+    assert!(key.contains("allocations_do_not_conflict"));
+    let first = allocate(&key)?;
+    let second = allocate(&key)?;
+    assert_ne!(first.port, second.port);
+    assert_ne!(first.database_dir, second.database_dir);
+    assert!(first.database_dir.is_dir());
+    assert_eq!(std::fs::read_dir(&first.database_dir).unwrap().count(), 0);
+    Ok(())
+}
+
+#[test]
+fn allocation_is_deterministic() -> Result<(), PgEmbedError> {
+    let port = allocate("deterministic")?.port;
+    // the released port is handed out again
+    assert_eq!(allocate("deterministic")?.port, port);
+    Ok(())
+}
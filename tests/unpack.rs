@@ -0,0 +1,66 @@
+use std::path::Path;
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use pg_embed::pg_errors::{PgEmbedError, PgEmbedErrorType};
+use pg_embed::pg_unpack::{unpack_archive, ArchiveFormat};
+
+fn write_tar_gz(path: &Path, entry_name: &str, content: &[u8]) {
+    let file = std::fs::File::create(path).unwrap();
+    let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+    let mut header = tar::Header::new_gnu();
+    // bypass the path validation of set_path to allow unsafe entry names
+    let name = &mut header.as_old_mut().name;
+    name[..entry_name.len()].copy_from_slice(entry_name.as_bytes());
+    header.set_size(content.len() as u64);
+    header.set_mode(0o755);
+    header.set_cksum();
+    builder.append(&header, content).unwrap();
+    builder.into_inner().unwrap().finish().unwrap();
+}
+
+#[test]
+fn detect_archive_format() {
+    assert_eq!(
+        ArchiveFormat::detect(&[0x50, 0x4b, 0x03, 0x04, 0x00]),
+        Some(ArchiveFormat::Zip)
+    );
+    assert_eq!(
+        ArchiveFormat::detect(&[0x1f, 0x8b, 0x08]),
+        Some(ArchiveFormat::TarGz)
+    );
+    assert_eq!(
+        ArchiveFormat::detect(&[0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00]),
+        Some(ArchiveFormat::TarXz)
+    );
+    assert_eq!(
+        ArchiveFormat::detect(&[0x28, 0xb5, 0x2f, 0xfd]),
+        Some(ArchiveFormat::TarZst)
+    );
+    assert_eq!(ArchiveFormat::detect(b"plain text"), None);
+}
+
+#[test]
+fn unpack_tar_gz() -> Result<(), PgEmbedError> {
+    let dir = tempfile::tempdir().unwrap();
+    let archive = dir.path().join("pg.tar.gz");
+    write_tar_gz(&archive, "bin/initdb", b"#!/bin/sh");
+    let target = dir.path().join("target");
+    unpack_archive(&archive, &target)?;
+    assert_eq!(
+        std::fs::read(target.join("bin/initdb")).unwrap(),
+        b"#!/bin/sh"
+    );
+    Ok(())
+}
+
+#[test]
+fn reject_entry_escaping_target_dir() {
+    let dir = tempfile::tempdir().unwrap();
+    let archive = dir.path().join("pg.tar.gz");
+    write_tar_gz(&archive, "../evil", b"evil");
+    let target = dir.path().join("target");
+    let err = unpack_archive(&archive, &target).unwrap_err();
+    assert_eq!(err.error_type, PgEmbedErrorType::UnsafeArchive);
+    assert!(!dir.path().join("evil").exists());
+}